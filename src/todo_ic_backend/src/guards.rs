@@ -0,0 +1,162 @@
+//! Caller checks named in the `guard =` attribute of every endpoint, and the
+//! `canister_inspect_message` filter in front of them. A guard runs before
+//! the endpoint's arguments are decoded, and a call it rejects never reaches
+//! the service layer.
+
+use crate::rate_limit::{has_token, take_token};
+use crate::storage::{get_config, is_admin, is_banned};
+use crate::types::Config;
+use candid::Principal;
+use ic_cdk::api::is_controller;
+use ic_cdk::api::time;
+use ic_cdk::caller;
+
+/// Update methods open to every allowed caller.
+const UPDATE_METHODS: &[&str] = &[
+    "ack_notifications",
+    "add_subtask",
+    "add_todo",
+    "add_todos",
+    "create_list",
+    "delete_list",
+    "delete_todo",
+    "delete_todos",
+    "empty_trash",
+    "move_subtree",
+    "move_todo",
+    "restore_todo",
+    "revoke_share",
+    "share_list",
+    "update_list",
+    "update_todo",
+    "update_todo_completed",
+    "update_todo_text",
+    "update_todos",
+];
+
+/// Update methods guarded by `caller_is_admin`.
+const ADMIN_UPDATE_METHODS: &[&str] = &[
+    "add_admin",
+    "ban_principal",
+    "remove_admin",
+    "run_maintenance",
+    "set_config",
+    "unban_principal",
+];
+
+/// Room per batch item for everything but the todo text: tags, the Candid
+/// framing of the optional fields and the recurrence rule.
+const INGRESS_ITEM_OVERHEAD_BYTES: u64 = 1024;
+
+/// Admins are the canister's controllers and the principals in the admin
+/// set, so a controller can never lock itself out.
+pub fn is_admin_principal(principal: Principal) -> bool {
+    is_controller(&principal) || is_admin(principal)
+}
+
+/// Guard of the admin endpoints.
+pub fn caller_is_admin() -> Result<(), String> {
+    check_admin(caller())
+}
+
+fn check_admin(principal: Principal) -> Result<(), String> {
+    if is_admin_principal(principal) {
+        Ok(())
+    } else {
+        Err("caller is not an admin".to_string())
+    }
+}
+
+fn check_access(principal: Principal, config: &Config) -> Result<(), String> {
+    if principal == Principal::anonymous() && !config.allow_anonymous {
+        return Err("anonymous callers are not allowed".to_string());
+    }
+    if is_banned(principal) {
+        return Err("caller is banned".to_string());
+    }
+    Ok(())
+}
+
+/// Guard of the query endpoints.
+pub fn caller_is_allowed() -> Result<(), String> {
+    check_access(caller(), &get_config())
+}
+
+/// Guard of the update endpoints: `caller_is_allowed`, then one token from
+/// the caller's rate limit bucket. Admins are not rate limited.
+pub fn caller_can_update() -> Result<(), String> {
+    let caller = caller();
+    let config = get_config();
+    check_access(caller, &config)?;
+    if is_admin_principal(caller) {
+        return Ok(());
+    }
+    take_token(caller, &config, time()).map_err(|retry_after_nanos| {
+        format!(
+            "rate limit exceeded; retry in {} ms",
+            retry_after_nanos.div_ceil(1_000_000)
+        )
+    })
+}
+
+/// Largest argument an ingress message may carry: a full batch of todos at
+/// the longest allowed text.
+fn max_ingress_arg_bytes(config: &Config) -> u64 {
+    config.max_batch_size * (config.max_todo_text_length + INGRESS_ITEM_OVERHEAD_BYTES)
+}
+
+/// Screens an ingress message before it goes through consensus, with the
+/// reason it is dropped as the error. This is a cheap, single-replica filter
+/// for obvious abuse; the endpoint guards still run on every replica and
+/// remain the actual checks. Queries are answered by query calls, so ingress
+/// naming one is dropped along with unknown methods.
+pub fn inspect_ingress(method: &str, arg_bytes: u64) -> Result<(), String> {
+    let caller = caller();
+    let config = get_config();
+    if ADMIN_UPDATE_METHODS.contains(&method) {
+        return check_admin(caller);
+    }
+    if !UPDATE_METHODS.contains(&method) {
+        return Err(format!("unknown update method {}", method));
+    }
+    check_access(caller, &config)?;
+    let max_bytes = max_ingress_arg_bytes(&config);
+    if arg_bytes > max_bytes {
+        return Err(format!("argument exceeds {} bytes", max_bytes));
+    }
+    if !is_admin_principal(caller) && !has_token(caller, &config, time()) {
+        return Err("rate limit exceeded".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid_parser::utils::CandidSource;
+
+    #[test]
+    fn every_update_method_is_screened_exactly_once() {
+        let generated = crate::__export_service();
+        let (env, actor) = CandidSource::Text(&generated)
+            .load()
+            .expect("failed to parse the exported interface");
+        let actor = actor.expect("the interface has no service");
+        let methods = env.as_service(&actor).expect("actor is not a service");
+
+        for (name, func) in methods {
+            let func = env.as_func(func).expect("method is not a function");
+            let listed = UPDATE_METHODS.contains(&name.as_str()) as usize
+                + ADMIN_UPDATE_METHODS.contains(&name.as_str()) as usize;
+            let expected = if func.is_query() { 0 } else { 1 };
+            assert_eq!(listed, expected, "{} is listed {} times", name, listed);
+        }
+        for name in UPDATE_METHODS.iter().chain(ADMIN_UPDATE_METHODS) {
+            assert!(
+                methods.iter().any(|(method, _)| method == name),
+                "{} is not an exported method",
+                name
+            );
+        }
+    }
+}
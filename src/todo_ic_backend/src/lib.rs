@@ -1,66 +1,254 @@
+mod guards;
+mod migrations;
+mod rate_limit;
+mod search;
 mod service;
 mod storage;
+mod timers;
 mod types;
 
-use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
+use candid::Principal;
+use guards::{caller_can_update, caller_is_admin, caller_is_allowed, inspect_ingress};
+use ic_cdk::api::call::{accept_message, arg_data_raw_size, method_name};
+use ic_cdk::{caller, init, inspect_message, post_upgrade, pre_upgrade, query, update};
+use migrations::{latest_schema_version, run_pending_migrations};
 use service::*;
-use storage::init_storage;
+use storage::{init_storage, set_schema_version};
+use timers::start_timers;
 use types::*;
 
 #[init]
-fn init() {
+fn init(args: Option<InitArgs>) {
     init_storage();
+    set_schema_version(latest_schema_version());
+    apply_init_args(args.unwrap_or_default());
+    start_timers();
 }
 
 #[pre_upgrade]
 fn pre_upgrade() {}
 
 #[post_upgrade]
-fn post_upgrade() {}
+fn post_upgrade(args: Option<InitArgs>) {
+    run_pending_migrations();
+    apply_init_args(args.unwrap_or_default());
+    start_timers();
+}
+
+/// Drops ingress that the guards would reject anyway before it costs a
+/// round of consensus. Trapping, rather than not accepting, tells the caller
+/// why.
+#[inspect_message]
+fn inspect_message() {
+    match inspect_ingress(&method_name(), arg_data_raw_size() as u64) {
+        Ok(()) => accept_message(),
+        Err(reason) => ic_cdk::trap(&reason),
+    }
+}
 
-#[update]
+#[update(guard = "caller_can_update")]
 pub fn add_todo(input: CreateTodoInput) -> TodoResult {
-    create_todo(input)
+    create_todo(caller(), input)
+}
+
+#[update(guard = "caller_can_update")]
+fn add_todos(inputs: Vec<CreateTodoInput>) -> TodoBatchResult {
+    create_todos(caller(), inputs)
 }
 
-#[query]
+#[update(guard = "caller_can_update")]
+fn add_subtask(parent_id: TodoId, input: CreateTodoInput) -> TodoResult {
+    service::add_subtask(caller(), parent_id, input)
+}
+
+#[query(guard = "caller_is_allowed")]
 fn get_todo(id: TodoId) -> TodoResult {
-    get_todo_by_id(id)
+    get_todo_by_id(caller(), id)
 }
 
-#[query]
+#[query(guard = "caller_is_allowed")]
+fn get_todo_with_subtasks(id: TodoId) -> SubtasksResult {
+    service::get_todo_with_subtasks(caller(), id)
+}
+
+#[update(guard = "caller_can_update")]
+fn move_subtree(id: TodoId, parent_id: Option<TodoId>) -> TodoResult {
+    service::move_subtree(caller(), id, parent_id)
+}
+
+#[query(guard = "caller_is_allowed")]
 fn get_all_todos(pagination: PaginationInput) -> TodosResult {
-    get_all_todos_paginated(pagination)
+    get_all_todos_paginated(caller(), pagination)
+}
+
+#[query(guard = "caller_is_allowed")]
+fn search_todos(query: String, pagination: PaginationInput) -> TodosResult {
+    service::search_todos(caller(), query, pagination)
+}
+
+#[update(guard = "caller_can_update")]
+fn update_todo(input: UpdateTodoInput) -> TodoResult {
+    service::update_todo(caller(), input)
 }
 
-#[update]
+#[update(guard = "caller_can_update")]
 fn update_todo_text(id: TodoId, text: String) -> TodoResult {
     let input = UpdateTodoInput {
         id,
         text: Some(text),
-        completed: None,
+        ..Default::default()
     };
-    service::update_todo(input)
+    service::update_todo(caller(), input)
 }
 
-#[update]
+#[update(guard = "caller_can_update")]
 fn update_todo_completed(id: TodoId, completed: bool) -> TodoResult {
     let input = UpdateTodoInput {
         id,
-        text: None,
         completed: Some(completed),
+        ..Default::default()
     };
-    service::update_todo(input)
+    service::update_todo(caller(), input)
+}
+
+#[update(guard = "caller_can_update")]
+fn update_todos(inputs: Vec<UpdateTodoInput>) -> TodoBatchResult {
+    service::update_todos(caller(), inputs)
+}
+
+#[update(guard = "caller_can_update")]
+fn move_todo(id: TodoId, before: Option<TodoId>, after: Option<TodoId>) -> TodoResult {
+    service::move_todo(caller(), id, before, after)
 }
 
-#[update]
+#[update(guard = "caller_can_update")]
 fn delete_todo(id: TodoId) -> DeleteResult {
-    delete_todo_by_id(id)
+    delete_todo_by_id(caller(), id)
 }
 
-#[query]
+#[update(guard = "caller_can_update")]
+fn delete_todos(ids: Vec<TodoId>) -> DeleteBatchResult {
+    service::delete_todos(caller(), ids)
+}
+
+#[query(guard = "caller_is_allowed")]
+fn list_trash(pagination: PaginationInput) -> TrashResult {
+    service::list_trash(caller(), pagination)
+}
+
+#[update(guard = "caller_can_update")]
+fn restore_todo(id: TodoId) -> TodoResult {
+    service::restore_todo(caller(), id)
+}
+
+#[update(guard = "caller_can_update")]
+fn empty_trash() -> u64 {
+    service::empty_trash(caller())
+}
+
+#[query(guard = "caller_is_allowed")]
 fn get_todo_count() -> u64 {
-    service::get_todo_count()
+    service::get_todo_count(caller())
+}
+
+#[query(guard = "caller_is_allowed")]
+fn get_notifications() -> Vec<Notification> {
+    service::get_notifications(caller())
+}
+
+#[update(guard = "caller_can_update")]
+fn ack_notifications(ids: Vec<NotificationId>) -> u64 {
+    service::ack_notifications(caller(), ids)
+}
+
+#[update(guard = "caller_can_update")]
+fn create_list(input: CreateListInput) -> ListResult {
+    service::create_list(caller(), input)
+}
+
+#[query(guard = "caller_is_allowed")]
+fn get_lists(include_archived: bool) -> Vec<TodoList> {
+    service::get_lists(caller(), include_archived)
+}
+
+#[update(guard = "caller_can_update")]
+fn update_list(input: UpdateListInput) -> ListResult {
+    service::update_list(caller(), input)
+}
+
+#[update(guard = "caller_can_update")]
+fn delete_list(id: ListId) -> DeleteResult {
+    service::delete_list(caller(), id)
+}
+
+#[update(guard = "caller_can_update")]
+fn share_list(list_id: ListId, principal: Principal, role: ListRole) -> ShareResult {
+    service::share_list(caller(), list_id, principal, role)
+}
+
+#[update(guard = "caller_can_update")]
+fn revoke_share(list_id: ListId, principal: Principal) -> DeleteResult {
+    service::revoke_share(caller(), list_id, principal)
+}
+
+#[query(guard = "caller_is_allowed")]
+fn get_list_shares(list_id: ListId) -> SharesResult {
+    service::get_list_shares(caller(), list_id)
+}
+
+#[query(guard = "caller_is_allowed")]
+fn list_shared_with_me() -> Vec<SharedList> {
+    service::list_shared_with_me(caller())
+}
+
+#[update(guard = "caller_is_admin")]
+fn add_admin(principal: Principal) -> bool {
+    grant_admin(principal)
+}
+
+#[update(guard = "caller_is_admin")]
+fn remove_admin(principal: Principal) -> bool {
+    revoke_admin(principal)
+}
+
+#[query(guard = "caller_is_admin")]
+fn list_admins() -> Vec<Principal> {
+    service::list_admins()
+}
+
+#[update(guard = "caller_is_admin")]
+fn ban_principal(principal: Principal) -> bool {
+    service::ban_principal(principal)
+}
+
+#[update(guard = "caller_is_admin")]
+fn unban_principal(principal: Principal) -> bool {
+    service::unban_principal(principal)
+}
+
+#[query(guard = "caller_is_admin")]
+fn list_banned() -> Vec<Principal> {
+    service::list_banned()
+}
+
+#[query(guard = "caller_is_admin")]
+fn get_config() -> Config {
+    storage::get_config()
+}
+
+#[update(guard = "caller_is_admin")]
+fn set_config(config: Config) -> ConfigResult {
+    update_config(config)
+}
+
+#[query(guard = "caller_is_admin")]
+fn get_global_stats() -> GlobalStats {
+    get_stats()
+}
+
+#[update(guard = "caller_is_admin")]
+fn run_maintenance(task: MaintenanceTask) -> u64 {
+    service::run_maintenance(task)
 }
 
 candid::export_service!();
@@ -69,3 +257,46 @@ candid::export_service!();
 fn export_candid() -> String {
     __export_service()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid_parser::utils::{service_compatible, CandidSource};
+    use std::path::PathBuf;
+
+    fn did_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("todo_ic_backend.did")
+    }
+
+    #[test]
+    fn candid_interface_is_compatible_with_committed_did() {
+        let generated = __export_service();
+        let committed = did_path();
+
+        service_compatible(
+            CandidSource::Text(&generated),
+            CandidSource::File(committed.as_path()),
+        )
+        .expect("Candid interface is incompatible with todo_ic_backend.did");
+    }
+
+    #[test]
+    fn committed_did_is_the_generated_interface() {
+        let committed =
+            std::fs::read_to_string(did_path()).expect("failed to read todo_ic_backend.did");
+
+        assert_eq!(
+            committed,
+            __export_service(),
+            "todo_ic_backend.did is out of date; run `make did`"
+        );
+    }
+
+    /// Regenerates `todo_ic_backend.did` from the Rust types. Run through `make did`.
+    #[test]
+    #[ignore]
+    fn save_candid_interface() {
+        std::fs::write(did_path(), __export_service())
+            .expect("failed to write todo_ic_backend.did");
+    }
+}
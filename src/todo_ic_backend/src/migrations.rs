@@ -0,0 +1,165 @@
+use crate::storage::{
+    clear_owner_bytes, get_schema_version, index_todo, index_todo_bytes, index_todo_rank,
+    index_todo_text, set_schema_version, with_todo_store, with_todo_store_mut,
+};
+use crate::types::rank::evenly_spaced_ranks;
+use crate::types::{ListId, TodoId};
+use candid::Principal;
+use std::collections::BTreeMap;
+
+/// One step in the stable-memory schema history. `run` turns data written
+/// under `version - 1` into the layout expected by `version`; it may rewrite
+/// `TodoStorage` records or copy data into a new virtual memory.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub run: fn(),
+}
+
+/// Ordered migration registry. Append new steps with the next version number;
+/// never reorder, renumber or remove entries that have shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "re-encode JSON todos in the versioned Candid format",
+        run: reencode_todos,
+    },
+    Migration {
+        version: 2,
+        description: "build the per-owner created_at index and todo counts",
+        run: build_created_index,
+    },
+    Migration {
+        version: 3,
+        description: "build the search index over todo text",
+        run: build_search_index,
+    },
+    Migration {
+        version: 4,
+        description: "rank every todo for manual ordering",
+        run: assign_ranks,
+    },
+    Migration {
+        version: 5,
+        description: "count the bytes each owner stores",
+        run: count_owner_bytes,
+    },
+];
+
+pub fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Runs every migration newer than the stored schema version, recording the
+/// version after each step so an interrupted upgrade resumes where it stopped.
+pub fn run_pending_migrations() {
+    let current = get_schema_version();
+    let latest = latest_schema_version();
+
+    if current > latest {
+        ic_cdk::trap(&format!(
+            "Stable memory schema version {} is newer than the latest known version {}",
+            current, latest
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        ic_cdk::println!(
+            "Running migration {}: {}",
+            migration.version,
+            migration.description
+        );
+        (migration.run)();
+        set_schema_version(migration.version);
+    }
+}
+
+/// Records written as JSON before encoding versioning existed are decoded
+/// through the legacy path and re-inserted in the current encoding.
+fn reencode_todos() {
+    let ids: Vec<TodoId> = with_todo_store(|store| store.iter().map(|(id, _)| id).collect());
+
+    for id in ids {
+        with_todo_store_mut(|store| {
+            if let Some(todo) = store.get(&id) {
+                store.insert(id, todo);
+            }
+        });
+    }
+}
+
+/// Replaces the schema v1 `(owner, id)` index, whose memory is left unused,
+/// and covers todos created before ownership existed.
+fn build_created_index() {
+    with_todo_store(|store| {
+        for (_, todo) in store.iter() {
+            index_todo(&todo);
+        }
+    });
+}
+
+/// Todos stored before schema v3 have no search index entries.
+fn build_search_index() {
+    with_todo_store(|store| {
+        for (_, todo) in store.iter() {
+            index_todo_text(&todo);
+        }
+    });
+}
+
+/// `(created_at, id)` of each todo, grouped by owner and list.
+type RankGroups = BTreeMap<(Principal, Option<ListId>), Vec<(u64, TodoId)>>;
+
+/// Todos stored before schema v4 have no rank. Each list is ranked newest
+/// first, the order its todos were listed in until then.
+fn assign_ranks() {
+    let mut lists = RankGroups::new();
+    with_todo_store(|store| {
+        for (id, todo) in store.iter() {
+            lists
+                .entry((todo.owner, todo.list_id))
+                .or_default()
+                .push((todo.created_at, id));
+        }
+    });
+
+    for mut positions in lists.into_values() {
+        positions.sort_by(|a, b| b.cmp(a));
+        let ranks = evenly_spaced_ranks(positions.len());
+        for ((_, id), rank) in positions.into_iter().zip(ranks) {
+            let ranked = with_todo_store_mut(|store| {
+                let mut todo = store.get(&id)?;
+                todo.rank = rank;
+                store.insert(id, todo.clone());
+                Some(todo)
+            });
+            if let Some(todo) = ranked {
+                index_todo_rank(&todo);
+            }
+        }
+    }
+}
+
+/// Todos stored before schema v5 are missing from the per-owner byte totals.
+/// Those indexed by an earlier step of the same upgrade are already counted,
+/// so the totals are recounted from scratch.
+fn count_owner_bytes() {
+    clear_owner_bytes();
+    with_todo_store(|store| {
+        for (_, todo) in store.iter() {
+            index_todo_bytes(&todo);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_versions_are_consecutive() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1);
+        }
+    }
+}
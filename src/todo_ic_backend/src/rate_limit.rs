@@ -0,0 +1,129 @@
+//! Token buckets limiting how often each principal can make update calls.
+//!
+//! Buckets live on the heap only. They are lost on upgrade and rebuilt
+//! lazily: a principal without a bucket starts with a full one.
+
+use crate::types::Config;
+use candid::Principal;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const NANOS_PER_MINUTE: u64 = 60 * 1_000_000_000;
+/// Buckets kept before full ones are dropped; a full bucket is the same as
+/// no bucket.
+const MAX_TRACKED_PRINCIPALS: usize = 10_000;
+
+/// Tokens are counted in units of `1 / NANOS_PER_MINUTE`, so refilling at
+/// `rate_limit_per_minute` tokens a minute adds exactly that many units per
+/// nanosecond.
+#[derive(Clone, Copy)]
+struct TokenBucket {
+    units: u128,
+    refilled_at: u64,
+}
+
+impl TokenBucket {
+    fn refilled(self, config: &Config, now: u64) -> Self {
+        let elapsed = now.saturating_sub(self.refilled_at) as u128;
+        let units = self
+            .units
+            .saturating_add(elapsed * config.rate_limit_per_minute as u128)
+            .min(capacity(config));
+        Self {
+            units,
+            refilled_at: now,
+        }
+    }
+}
+
+fn capacity(config: &Config) -> u128 {
+    config.rate_limit_burst as u128 * NANOS_PER_MINUTE as u128
+}
+
+thread_local! {
+    static BUCKETS: RefCell<HashMap<Principal, TokenBucket>> = RefCell::new(HashMap::new());
+}
+
+fn current_bucket(principal: Principal, config: &Config, now: u64) -> TokenBucket {
+    let full = TokenBucket {
+        units: capacity(config),
+        refilled_at: now,
+    };
+    BUCKETS.with(|buckets| {
+        buckets
+            .borrow()
+            .get(&principal)
+            .map_or(full, |bucket| bucket.refilled(config, now))
+    })
+}
+
+/// Takes one token from `principal`'s bucket. An empty bucket is left as
+/// is, and the error is how many nanoseconds remain until it holds a token.
+pub fn take_token(principal: Principal, config: &Config, now: u64) -> Result<(), u64> {
+    if config.rate_limit_per_minute == 0 {
+        return Ok(());
+    }
+    let mut bucket = current_bucket(principal, config, now);
+    let token = NANOS_PER_MINUTE as u128;
+    if bucket.units < token {
+        let missing = token - bucket.units;
+        let per_nano = config.rate_limit_per_minute as u128;
+        return Err(missing.div_ceil(per_nano) as u64);
+    }
+    bucket.units -= token;
+
+    BUCKETS.with(|buckets| {
+        let mut buckets = buckets.borrow_mut();
+        if buckets.len() >= MAX_TRACKED_PRINCIPALS {
+            buckets.retain(|_, bucket| bucket.refilled(config, now).units < capacity(config));
+        }
+        buckets.insert(principal, bucket);
+    });
+    Ok(())
+}
+
+/// Whether `principal` has a token to spend, without spending it.
+pub fn has_token(principal: Principal, config: &Config, now: u64) -> bool {
+    config.rate_limit_per_minute == 0
+        || current_bucket(principal, config, now).units >= NANOS_PER_MINUTE as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(per_minute: u64, burst: u64) -> Config {
+        Config {
+            rate_limit_per_minute: per_minute,
+            rate_limit_burst: burst,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn a_burst_empties_the_bucket_and_time_refills_it() {
+        let principal = Principal::from_slice(&[7; 29]);
+        let config = config(60, 2);
+
+        assert_eq!(take_token(principal, &config, 0), Ok(()));
+        assert_eq!(take_token(principal, &config, 0), Ok(()));
+        assert!(!has_token(principal, &config, 0));
+        // One token a second at 60 a minute.
+        assert_eq!(take_token(principal, &config, 0), Err(1_000_000_000));
+        assert_eq!(
+            take_token(principal, &config, 400_000_000),
+            Err(600_000_000)
+        );
+        assert_eq!(take_token(principal, &config, 1_000_000_000), Ok(()));
+    }
+
+    #[test]
+    fn a_zero_rate_turns_limiting_off() {
+        let principal = Principal::from_slice(&[8; 29]);
+        let config = config(0, 0);
+
+        for _ in 0..10 {
+            assert_eq!(take_token(principal, &config, 0), Ok(()));
+        }
+    }
+}
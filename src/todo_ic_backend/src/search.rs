@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+
+/// Longest token kept in the search index, in bytes. Longer words are cut
+/// at a character boundary, so a query for the full word still matches as a
+/// prefix of the stored token.
+pub const MAX_TOKEN_LENGTH: usize = 32;
+
+/// Most distinct terms accepted in a single search query.
+pub const MAX_QUERY_TERMS: usize = 8;
+
+/// Splits `text` into lower-cased alphanumeric words, each truncated to
+/// `MAX_TOKEN_LENGTH` bytes. Used for both indexing and queries so the two
+/// always agree.
+pub fn tokenize(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| truncate_token(&word.to_lowercase()))
+        .collect()
+}
+
+fn truncate_token(word: &str) -> String {
+    let mut token = String::new();
+    for c in word.chars() {
+        if token.len() + c.len_utf8() > MAX_TOKEN_LENGTH {
+            break;
+        }
+        token.push(c);
+    }
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_lowercases_splits_and_dedupes() {
+        let tokens = tokenize("Buy milk, buy BREAD & eggs!");
+        let expected: BTreeSet<String> = ["buy", "milk", "bread", "eggs"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn tokenize_truncates_long_words_on_char_boundaries() {
+        let word = "é".repeat(40);
+        let tokens = tokenize(&word);
+        let token = tokens.iter().next().unwrap();
+        assert_eq!(token.len(), MAX_TOKEN_LENGTH);
+        assert!(word.starts_with(token.as_str()));
+    }
+}
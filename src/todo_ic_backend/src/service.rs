@@ -1,132 +1,1914 @@
-use crate::storage::{get_next_todo_id, with_todo_store, with_todo_store_mut};
+use crate::guards::is_admin_principal;
+use crate::search::{tokenize, MAX_QUERY_TERMS};
+use crate::storage::{
+    content_bytes, get_admins, get_banned, get_bytes_by_owner, get_child_ids, get_config,
+    get_descendant_ids, get_due_reminder_ids, get_expired_trash_ids, get_first_rank,
+    get_global_stats, get_list, get_list_ids_shared_with, get_lists_by_owner, get_next_list_id,
+    get_next_notification_id, get_next_todo_id, get_notifications_by_owner,
+    get_recurring_ids_due_by, get_share, get_shares_by_list, get_todo_count_by_owner,
+    get_todo_ids_by_due_date, get_todo_ids_by_list, get_todo_ids_by_rank, get_todo_ids_by_recency,
+    get_todo_ids_by_tag, get_todo_ids_by_token_prefix, get_trash_count_by_owner,
+    get_trashed_ids_by_owner, get_trashed_todo, index_todo, insert_admin, insert_ban, insert_list,
+    insert_notification, insert_share, insert_trashed_todo, reindex_todo, remove_admin, remove_ban,
+    remove_list, remove_notification, remove_share, remove_trashed_todo, scan_todos_by_recency,
+    set_config, unindex_todo, with_todo_store, with_todo_store_mut, RecencyPosition,
+};
+use crate::types::rank::{evenly_spaced_ranks, rank_between};
+use crate::types::recurrence::next_occurrence;
 use crate::types::*;
+use candid::Principal;
 use ic_cdk::api::time;
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
 
-const MAX_TODO_TEXT_LENGTH: usize = 1000;
-const MAX_PAGINATION_LIMIT: u64 = 100;
+/// Ceiling on `Config::max_page_limit` and `Config::max_batch_size`, keeping
+/// one call well inside the instruction limit.
+const MAX_CONFIGURABLE_LIMIT: u64 = 1000;
+/// Upper bound on todos purged per timer run or `empty_trash` call, keeping
+/// each well inside the instruction limit.
+const MAX_PURGE_PER_RUN: u64 = 500;
+/// How long before its due date the next occurrence of a recurring todo is
+/// created.
+const RECURRENCE_LOOKAHEAD_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Upper bound on occurrences created per timer run.
+const MAX_OCCURRENCES_PER_RUN: u64 = 500;
+/// Upper bound on reminders delivered per timer run.
+const MAX_REMINDERS_PER_RUN: u64 = 500;
+/// Largest inbox a principal can have; older notifications are dropped.
+const MAX_NOTIFICATIONS_PER_OWNER: usize = 100;
 
-pub fn validate_todo_text(text: &str) -> Result<(), String> {
+pub fn validate_todo_text(text: &str) -> Result<(), TodoError> {
     if text.trim().is_empty() {
-        return Err("Todo text cannot be empty".to_string());
+        return Err(TodoError::validation("text", "cannot be empty"));
     }
-    if text.len() > MAX_TODO_TEXT_LENGTH {
-        return Err(format!(
-            "Todo text cannot exceed {} characters",
-            MAX_TODO_TEXT_LENGTH
+    let max_length = get_config().max_todo_text_length;
+    if text.len() as u64 > max_length {
+        return Err(TodoError::validation(
+            "text",
+            format!("cannot exceed {} characters", max_length),
         ));
     }
     Ok(())
 }
 
-pub fn validate_pagination(pagination: &PaginationInput) -> Result<(), String> {
+/// Tags are compared case-insensitively and stored lower-cased.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalised, sorted and deduplicated copy of `tags`.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags.iter().map(|tag| normalize_tag(tag)).collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+pub fn validate_tag(field: &str, tag: &str) -> Result<(), TodoError> {
+    let tag = normalize_tag(tag);
+    if tag.is_empty() {
+        return Err(TodoError::validation(field, "cannot contain empty tags"));
+    }
+    if tag.len() > MAX_TAG_LENGTH {
+        return Err(TodoError::validation(
+            field,
+            format!("cannot contain tags longer than {} bytes", MAX_TAG_LENGTH),
+        ));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(TodoError::validation(
+            field,
+            "may only contain letters, digits, '-' and '_'",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_tags(tags: &[String]) -> Result<(), TodoError> {
+    for tag in tags {
+        validate_tag("tags", tag)?;
+    }
+    if normalize_tags(tags).len() > MAX_TAGS_PER_TODO {
+        return Err(TodoError::validation(
+            "tags",
+            format!("cannot contain more than {} tags", MAX_TAGS_PER_TODO),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_create_input(caller: Principal, input: &CreateTodoInput) -> Result<(), TodoError> {
+    validate_todo_text(&input.text)?;
+    if let Some(tags) = &input.tags {
+        validate_tags(tags)?;
+    }
+    if let Some(list_id) = input.list_id {
+        validate_list_reference(caller, list_id)?;
+    }
+    if let Some(rule) = &input.recurrence {
+        validate_recurrence(rule)?;
+        if input.due_at.is_none() {
+            return Err(TodoError::validation("recurrence", "requires a due date"));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_recurrence(rule: &Recurrence) -> Result<(), TodoError> {
+    match rule {
+        Recurrence::Daily => Ok(()),
+        Recurrence::Weekly { weekdays } => {
+            if weekdays.is_empty() {
+                return Err(TodoError::validation("recurrence", "must list a weekday"));
+            }
+            let mut sorted = weekdays.clone();
+            sorted.sort();
+            sorted.dedup();
+            if sorted.len() != weekdays.len() {
+                return Err(TodoError::validation(
+                    "recurrence",
+                    "cannot list a weekday twice",
+                ));
+            }
+            Ok(())
+        }
+        Recurrence::Monthly { day } if !(1..=31).contains(day) => Err(TodoError::validation(
+            "recurrence",
+            "day of the month must be between 1 and 31",
+        )),
+        Recurrence::Monthly { .. } => Ok(()),
+        Recurrence::EveryNDays { days } if !(1..=MAX_RECURRENCE_INTERVAL_DAYS).contains(days) => {
+            Err(TodoError::validation(
+                "recurrence",
+                format!(
+                    "interval must be between 1 and {} days",
+                    MAX_RECURRENCE_INTERVAL_DAYS
+                ),
+            ))
+        }
+        Recurrence::EveryNDays { .. } => Ok(()),
+    }
+}
+
+/// Todos may only be put into lists the caller can edit, and not into
+/// archived ones. Returns the list.
+pub fn validate_list_reference(caller: Principal, list_id: ListId) -> Result<TodoList, TodoError> {
+    let list = match get_list(list_id) {
+        Some(list) => list,
+        None => {
+            return Err(TodoError::validation(
+                "list_id",
+                "does not refer to one of your lists",
+            ))
+        }
+    };
+    match list_role(caller, &list) {
+        None => Err(TodoError::validation(
+            "list_id",
+            "does not refer to one of your lists",
+        )),
+        Some(ListRole::Viewer) => Err(TodoError::validation(
+            "list_id",
+            "is shared with you read-only",
+        )),
+        Some(_) if list.archived => Err(TodoError::validation("list_id", "is archived")),
+        Some(_) => Ok(list),
+    }
+}
+
+pub fn validate_list_name(name: &str) -> Result<(), TodoError> {
+    if name.trim().is_empty() {
+        return Err(TodoError::validation("name", "cannot be empty"));
+    }
+    if name.trim().len() > MAX_LIST_NAME_LENGTH {
+        return Err(TodoError::validation(
+            "name",
+            format!("cannot exceed {} characters", MAX_LIST_NAME_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// Colours are `#rrggbb` hex strings.
+pub fn validate_list_colour(colour: &str) -> Result<(), TodoError> {
+    let is_hex = colour.len() == 7
+        && colour.starts_with('#')
+        && colour[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex {
+        return Err(TodoError::validation("colour", "must look like #4c51bf"));
+    }
+    Ok(())
+}
+
+pub fn validate_batch_size(len: usize) -> Result<(), TodoError> {
+    let max_size = get_config().max_batch_size;
+    if len as u64 > max_size {
+        return Err(TodoError::validation(
+            "batch",
+            format!("cannot contain more than {} items", max_size),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_config(config: &Config) -> Result<(), TodoError> {
+    if config.max_todo_text_length == 0 || config.max_todo_text_length > MAX_TODO_TEXT_LENGTH as u64
+    {
+        return Err(TodoError::validation(
+            "max_todo_text_length",
+            format!("must be between 1 and {}", MAX_TODO_TEXT_LENGTH),
+        ));
+    }
+    if config.max_page_limit == 0 || config.max_page_limit > MAX_CONFIGURABLE_LIMIT {
+        return Err(TodoError::validation(
+            "max_page_limit",
+            format!("must be between 1 and {}", MAX_CONFIGURABLE_LIMIT),
+        ));
+    }
+    if config.max_batch_size == 0 || config.max_batch_size > MAX_CONFIGURABLE_LIMIT {
+        return Err(TodoError::validation(
+            "max_batch_size",
+            format!("must be between 1 and {}", MAX_CONFIGURABLE_LIMIT),
+        ));
+    }
+    // An empty bucket that refills would refuse every update.
+    if config.rate_limit_per_minute > 0 && config.rate_limit_burst == 0 {
+        return Err(TodoError::validation(
+            "rate_limit_burst",
+            "must be at least 1 while rate limiting is on",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_update_input(input: &UpdateTodoInput) -> Result<(), TodoError> {
+    if let Some(text) = &input.text {
+        validate_todo_text(text)?;
+    }
+    if let Some(tags) = &input.tags {
+        validate_tags(tags)?;
+    }
+    if let Some(Some(rule)) = &input.recurrence {
+        validate_recurrence(rule)?;
+    }
+    Ok(())
+}
+
+pub fn validate_pagination(pagination: &PaginationInput) -> Result<(), TodoError> {
     if pagination.limit == 0 {
-        return Err("Limit must be greater than 0".to_string());
+        return Err(TodoError::validation("limit", "must be greater than 0"));
     }
-    if pagination.limit > MAX_PAGINATION_LIMIT {
-        return Err(format!("Limit cannot exceed {}", MAX_PAGINATION_LIMIT));
+    let max_limit = get_config().max_page_limit;
+    if pagination.limit > max_limit {
+        return Err(TodoError::validation(
+            "limit",
+            format!("cannot exceed {}", max_limit),
+        ));
     }
     Ok(())
 }
 
-pub fn create_todo(input: CreateTodoInput) -> TodoResult {
-    if let Err(err) = validate_todo_text(&input.text) {
-        return TodoResult::Err(err);
+pub fn validate_query(pagination: &PaginationInput) -> Result<(), TodoError> {
+    if let Some(filter) = &pagination.filter {
+        if let (Some(after), Some(before)) = (filter.created_after, filter.created_before) {
+            if after > before {
+                return Err(TodoError::validation(
+                    "created_after",
+                    "cannot be later than created_before",
+                ));
+            }
+        }
+        if let (Some(after), Some(before)) = (filter.updated_after, filter.updated_before) {
+            if after > before {
+                return Err(TodoError::validation(
+                    "updated_after",
+                    "cannot be later than updated_before",
+                ));
+            }
+        }
+        if let Some(text) = &filter.text_contains {
+            if text.trim().is_empty() {
+                return Err(TodoError::validation("text_contains", "cannot be empty"));
+            }
+            let max_length = get_config().max_todo_text_length;
+            if text.len() as u64 > max_length {
+                return Err(TodoError::validation(
+                    "text_contains",
+                    format!("cannot exceed {} characters", max_length),
+                ));
+            }
+        }
+        if let (Some(after), Some(before)) = (filter.due_after, filter.due_before) {
+            if after > before {
+                return Err(TodoError::validation(
+                    "due_after",
+                    "cannot be later than due_before",
+                ));
+            }
+        }
+        if let Some(tag) = &filter.tag {
+            validate_tag("tag", tag)?;
+        }
+    }
+    if pagination.cursor.is_some() && pagination.sort.unwrap_or_default() != TodoSort::default() {
+        return Err(TodoError::validation(
+            "cursor",
+            "is only supported for the default newest-first sort",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks a search request and returns its distinct query terms. Search
+/// results are ranked, so cursors and explicit sorts do not apply.
+pub fn validate_search(
+    query: &str,
+    pagination: &PaginationInput,
+) -> Result<Vec<String>, TodoError> {
+    validate_pagination(pagination)?;
+    validate_query(pagination)?;
+    if pagination.cursor.is_some() {
+        return Err(TodoError::validation(
+            "cursor",
+            "is not supported for search",
+        ));
+    }
+    if pagination.sort.is_some() {
+        return Err(TodoError::validation(
+            "sort",
+            "is not supported for search; results are ranked by relevance",
+        ));
+    }
+    let max_length = get_config().max_todo_text_length;
+    if query.len() as u64 > max_length {
+        return Err(TodoError::validation(
+            "query",
+            format!("cannot exceed {} characters", max_length),
+        ));
+    }
+
+    let terms: Vec<String> = tokenize(query).into_iter().collect();
+    if terms.is_empty() {
+        return Err(TodoError::validation(
+            "query",
+            "must contain at least one word",
+        ));
+    }
+    if terms.len() > MAX_QUERY_TERMS {
+        return Err(TodoError::validation(
+            "query",
+            format!(
+                "cannot contain more than {} distinct words",
+                MAX_QUERY_TERMS
+            ),
+        ));
+    }
+    Ok(terms)
+}
+
+/// Cursors are opaque to clients: the hex-encoded `(created_at, id)` of the
+/// last todo on the previous page.
+pub fn encode_cursor(todo: &Todo) -> String {
+    format!("{:016x}{:016x}", todo.created_at, todo.id)
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<RecencyPosition, TodoError> {
+    let invalid = || TodoError::validation("cursor", "is not a valid page cursor");
+    if cursor.len() != 32 || !cursor.is_ascii() {
+        return Err(invalid());
+    }
+    let created_at = u64::from_str_radix(&cursor[..16], 16).map_err(|_| invalid())?;
+    let id = u64::from_str_radix(&cursor[16..], 16).map_err(|_| invalid())?;
+    Ok((created_at, id))
+}
+
+pub fn ensure_owner(todo: &Todo, caller: Principal) -> Result<(), TodoError> {
+    if todo.owner != caller {
+        return Err(TodoError::Unauthorized);
+    }
+    Ok(())
+}
+
+/// The caller's role on `list`: its creator owns it, anyone else has the
+/// role it is shared with them as, if any. Read from the ACL on every call,
+/// so a revoked share stops working immediately.
+fn list_role(caller: Principal, list: &TodoList) -> Option<ListRole> {
+    if list.owner == caller {
+        Some(ListRole::Owner)
+    } else {
+        get_share(list.id, caller)
+    }
+}
+
+/// The caller's role on `todo`: its owner owns it, anyone else has their
+/// role on its list.
+fn todo_role(caller: Principal, todo: &Todo) -> Option<ListRole> {
+    if todo.owner == caller {
+        Some(ListRole::Owner)
+    } else {
+        todo.list_id.and_then(|list_id| get_share(list_id, caller))
+    }
+}
+
+fn can_read(caller: Principal, todo: &Todo) -> bool {
+    todo_role(caller, todo).is_some()
+}
+
+/// Changing a todo takes at least the editor role on it.
+pub fn ensure_can_edit(todo: &Todo, caller: Principal) -> Result<(), TodoError> {
+    match todo_role(caller, todo) {
+        Some(role) if role >= ListRole::Editor => Ok(()),
+        _ => Err(TodoError::Unauthorized),
+    }
+}
+
+/// `list_id` if the caller has the owner role on it.
+fn owned_list(caller: Principal, list_id: ListId) -> Result<TodoList, TodoError> {
+    let list = get_list(list_id).ok_or(TodoError::NotFound { id: list_id })?;
+    match list_role(caller, &list) {
+        Some(ListRole::Owner) => Ok(list),
+        _ => Err(TodoError::Unauthorized),
+    }
+}
+
+/// Owner of a todo the caller files in `list_id`. Todos in a shared list
+/// belong to the list's creator, whoever adds them.
+fn owner_for(caller: Principal, list_id: Option<ListId>) -> Principal {
+    list_id.and_then(get_list).map_or(caller, |list| list.owner)
+}
+
+/// A subtask always has the same owner as its parent.
+fn ensure_same_owner(parent: &Todo, owner: Principal) -> Result<(), TodoError> {
+    if parent.owner != owner {
+        return Err(TodoError::validation(
+            "parent_id",
+            "must belong to the owner of the subtask's list",
+        ));
+    }
+    Ok(())
+}
+
+/// New todos count against their owner's `max_todos_per_owner`, and their
+/// `bytes` of text and tags against `max_bytes_per_owner`.
+fn ensure_todo_quota(owner: Principal, bytes: u64) -> Result<(), TodoError> {
+    ensure_todos_quota(owner, 1, bytes)
+}
+
+/// `ensure_todo_quota` for `count` new todos of `bytes` in total.
+fn ensure_todos_quota(owner: Principal, count: u64, bytes: u64) -> Result<(), TodoError> {
+    let limit = get_config().max_todos_per_owner;
+    if get_todo_count_by_owner(owner) + count > limit {
+        return Err(TodoError::QuotaExceeded {
+            quota: "max_todos_per_owner".to_string(),
+            limit,
+        });
+    }
+    ensure_byte_quota(owner, 0, bytes)
+}
+
+/// Checks that replacing `freed` bytes of `owner`'s todos with `added`
+/// bytes stays within `max_bytes_per_owner`. Edits that do not grow a todo
+/// always pass, so an owner over a lowered quota can still trim their todos.
+fn ensure_byte_quota(owner: Principal, freed: u64, added: u64) -> Result<(), TodoError> {
+    if added <= freed {
+        return Ok(());
+    }
+    let limit = get_config().max_bytes_per_owner;
+    if get_bytes_by_owner(owner).saturating_sub(freed) + added > limit {
+        return Err(TodoError::QuotaExceeded {
+            quota: "max_bytes_per_owner".to_string(),
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Bytes a todo created from `input` will count against its owner's quota.
+fn input_bytes(input: &CreateTodoInput) -> u64 {
+    let tags = normalize_tags(input.tags.as_deref().unwrap_or_default());
+    content_bytes(input.text.trim(), &tags)
+}
+
+/// Rejects a write based on a stale read of `todo`.
+pub fn ensure_unchanged(todo: &Todo, expected_version: Option<u64>) -> Result<(), TodoError> {
+    match expected_version {
+        Some(expected) if expected != todo.version => Err(TodoError::Conflict {
+            id: todo.id,
+            version: todo.version,
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub fn create_todo(caller: Principal, input: CreateTodoInput) -> TodoResult {
+    let check = validate_create_input(caller, &input)
+        .and_then(|()| ensure_todo_quota(owner_for(caller, input.list_id), input_bytes(&input)));
+    match check {
+        Ok(()) => TodoResult::Ok(insert_todo(caller, input, None)),
+        Err(err) => TodoResult::Err(err),
+    }
+}
+
+/// Creates a subtask of `parent_id` in its parent's list. A `list_id` naming
+/// any other list is rejected, so a tree never spans lists.
+pub fn add_subtask(caller: Principal, parent_id: TodoId, mut input: CreateTodoInput) -> TodoResult {
+    let parent = match validate_parent(caller, parent_id, 0) {
+        Ok(parent) => parent,
+        Err(err) => return TodoResult::Err(err),
+    };
+    match input.list_id {
+        None => input.list_id = parent.list_id,
+        Some(list_id) if parent.list_id != Some(list_id) => {
+            return TodoResult::Err(TodoError::validation(
+                "list_id",
+                "must be the list of the parent todo",
+            ))
+        }
+        Some(_) => {}
+    }
+
+    let owner = owner_for(caller, input.list_id);
+    let check = validate_create_input(caller, &input)
+        .and_then(|()| ensure_same_owner(&parent, owner))
+        .and_then(|()| ensure_todo_quota(owner, input_bytes(&input)));
+    match check {
+        Ok(()) => TodoResult::Ok(insert_todo(caller, input, Some(parent_id))),
+        Err(err) => TodoResult::Err(err),
+    }
+}
+
+/// Creates every valid input and reports the rest. All inputs are validated
+/// before the first todo is written.
+pub fn create_todos(caller: Principal, inputs: Vec<CreateTodoInput>) -> TodoBatchResult {
+    if let Err(err) = validate_batch_size(inputs.len()) {
+        return TodoBatchResult::Err(err);
     }
 
+    let checks: Vec<Result<(), TodoError>> = inputs
+        .iter()
+        .map(|input| validate_create_input(caller, input))
+        .collect();
+
+    let results = inputs
+        .into_iter()
+        .zip(checks)
+        .map(|(input, check)| {
+            // The quota is checked as each todo is written, so a batch fills
+            // it up and reports the rest.
+            let owner = owner_for(caller, input.list_id);
+            match check.and_then(|()| ensure_todo_quota(owner, input_bytes(&input))) {
+                Ok(()) => TodoResult::Ok(insert_todo(caller, input, None)),
+                Err(err) => TodoResult::Err(err),
+            }
+        })
+        .collect();
+    TodoBatchResult::Ok(results)
+}
+
+/// Stores a new todo added by `caller`. `input` and `parent_id` must already
+/// be validated.
+fn insert_todo(caller: Principal, input: CreateTodoInput, parent_id: Option<TodoId>) -> Todo {
     let now = time();
     let id = get_next_todo_id();
+    let owner = owner_for(caller, input.list_id);
+    let rank = top_rank(owner, input.list_id);
 
     let todo = Todo {
         id,
+        owner,
         text: input.text.trim().to_string(),
         completed: false,
         created_at: now,
         updated_at: now,
+        priority: input.priority,
+        due_at: input.due_at,
+        tags: normalize_tags(&input.tags.unwrap_or_default()),
+        list_id: input.list_id,
+        parent_id,
+        rank,
+        recurrence: input.recurrence,
+        remind_at: input.remind_at,
+        version: 0,
     };
 
     with_todo_store_mut(|store| {
         store.insert(id, todo.clone());
     });
+    index_todo(&todo);
 
-    TodoResult::Ok(todo)
+    todo
 }
 
-pub fn get_todo_by_id(id: TodoId) -> TodoResult {
+pub fn get_todo_by_id(caller: Principal, id: TodoId) -> TodoResult {
     with_todo_store(|store| match store.get(&id) {
-        Some(todo) => TodoResult::Ok(todo),
-        None => TodoResult::Err("Todo not found".to_string()),
+        Some(todo) if can_read(caller, &todo) => TodoResult::Ok(todo),
+        _ => TodoResult::Err(TodoError::NotFound { id }),
     })
 }
 
-pub fn get_all_todos_paginated(pagination: PaginationInput) -> TodosResult {
-    if let Err(err) = validate_pagination(&pagination) {
-        return TodosResult::Err(err);
+pub fn get_todo_with_subtasks(caller: Principal, id: TodoId) -> SubtasksResult {
+    let todo = match get_todo_by_id(caller, id) {
+        TodoResult::Ok(todo) => todo,
+        TodoResult::Err(err) => return SubtasksResult::Err(err),
+    };
+
+    let subtasks = with_todo_store(|store| {
+        get_child_ids(id)
+            .iter()
+            .filter_map(|id| store.get(id))
+            .filter(|subtask| can_read(caller, subtask))
+            .collect()
+    });
+    let completion_percent = completion_percent(&todo);
+
+    SubtasksResult::Ok(Box::new(TodoWithSubtasks {
+        todo,
+        subtasks,
+        completion_percent,
+    }))
+}
+
+/// Share of the subtasks below `todo`, at any depth, that are completed.
+fn completion_percent(todo: &Todo) -> u8 {
+    let descendants = get_descendant_ids(todo.id);
+    if descendants.is_empty() {
+        return if todo.completed { 100 } else { 0 };
     }
 
+    let completed = with_todo_store(|store| {
+        descendants
+            .iter()
+            .filter_map(|id| store.get(id))
+            .filter(|subtask| subtask.completed)
+            .count()
+    });
+    (completed * 100 / descendants.len()) as u8
+}
+
+/// `parent_id` must be a todo the caller can edit, with room below it for a
+/// subtree `height` levels deep under the new child.
+fn validate_parent(caller: Principal, parent_id: TodoId, height: usize) -> Result<Todo, TodoError> {
+    let parent = match with_todo_store(|store| store.get(&parent_id)) {
+        Some(parent) if ensure_can_edit(&parent, caller).is_ok() => parent,
+        _ => {
+            return Err(TodoError::validation(
+                "parent_id",
+                "does not refer to one of your todos",
+            ))
+        }
+    };
+    if todo_depth(&parent) + 1 + height > MAX_SUBTASK_DEPTH {
+        return Err(TodoError::validation(
+            "parent_id",
+            format!(
+                "subtasks cannot be nested more than {} levels deep",
+                MAX_SUBTASK_DEPTH
+            ),
+        ));
+    }
+    Ok(parent)
+}
+
+/// Number of ancestors above `todo`.
+fn todo_depth(todo: &Todo) -> usize {
     with_todo_store(|store| {
-        let mut all_todos: Vec<Todo> = store.iter().map(|(_, todo)| todo).collect();
-        all_todos.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        let mut depth = 0;
+        let mut parent_id = todo.parent_id;
+        while let Some(id) = parent_id {
+            depth += 1;
+            parent_id = store.get(&id).and_then(|parent| parent.parent_id);
+        }
+        depth
+    })
+}
 
-        let total_count = all_todos.len() as u64;
-        let start_index = pagination.offset as usize;
+/// Number of subtask levels below `id`.
+fn subtree_height(id: TodoId) -> usize {
+    let mut height = 0;
+    let mut level = get_child_ids(id);
+    while !level.is_empty() {
+        height += 1;
+        level = level.into_iter().flat_map(get_child_ids).collect();
+    }
+    height
+}
 
-        if start_index >= all_todos.len() {
-            return TodosResult::Ok(TodosPage {
-                todos: vec![],
-                total_count,
-                has_more: false,
+/// Moves a todo, with all its subtasks, under `parent_id`, or to the top
+/// level when `parent_id` is `None`. A subtree moved under a todo in another
+/// list moves into that list.
+pub fn move_subtree(caller: Principal, id: TodoId, parent_id: Option<TodoId>) -> TodoResult {
+    match reparent(caller, id, parent_id) {
+        Ok(todo) => TodoResult::Ok(todo),
+        Err(err) => TodoResult::Err(err),
+    }
+}
+
+fn reparent(caller: Principal, id: TodoId, parent_id: Option<TodoId>) -> Result<Todo, TodoError> {
+    let previous = with_todo_store(|store| store.get(&id)).ok_or(TodoError::NotFound { id })?;
+    ensure_can_edit(&previous, caller)?;
+
+    let mut todo = previous.clone();
+    if let Some(parent_id) = parent_id {
+        if parent_id == id || get_descendant_ids(id).contains(&parent_id) {
+            return Err(TodoError::validation(
+                "parent_id",
+                "cannot be the todo itself or one of its subtasks",
+            ));
+        }
+        let parent = validate_parent(caller, parent_id, subtree_height(id))?;
+        ensure_same_owner(&parent, previous.owner)?;
+        if parent.list_id != previous.list_id {
+            if let Some(list_id) = parent.list_id {
+                validate_list_reference(caller, list_id)?;
+            }
+            todo.list_id = parent.list_id;
+            todo.rank = top_rank(todo.owner, todo.list_id);
+        }
+    }
+
+    todo.parent_id = parent_id;
+    todo.updated_at = time();
+    todo.version += 1;
+    with_todo_store_mut(|store| {
+        store.insert(id, todo.clone());
+    });
+    reindex_todo(&previous, &todo);
+    if todo.list_id != previous.list_id {
+        move_subtasks_to_list(&todo);
+    }
+
+    Ok(todo)
+}
+
+pub fn get_all_todos_paginated(caller: Principal, pagination: PaginationInput) -> TodosResult {
+    if let Err(err) = validate_pagination(&pagination).and_then(|_| validate_query(&pagination)) {
+        return TodosResult::Err(err);
+    }
+
+    let after = match pagination.cursor.as_deref().map(decode_cursor).transpose() {
+        Ok(after) => after,
+        Err(err) => return TodosResult::Err(err),
+    };
+
+    let sort = pagination.sort.unwrap_or_default();
+    let page = match &pagination.filter {
+        None if sort == TodoSort::default() => newest_first_page(caller, after, &pagination),
+        _ => filtered_page(caller, after, &pagination, sort),
+    };
+    TodosResult::Ok(page)
+}
+
+/// Unfiltered newest-first listing: a bounded range scan of the created_at index.
+fn newest_first_page(
+    caller: Principal,
+    after: Option<RecencyPosition>,
+    pagination: &PaginationInput,
+) -> TodosPage {
+    // Read one extra id to learn whether another page follows.
+    let mut ids = get_todo_ids_by_recency(caller, after, pagination.offset, pagination.limit + 1);
+    let has_more = ids.len() as u64 > pagination.limit;
+    ids.truncate(pagination.limit as usize);
+
+    let todos: Vec<Todo> =
+        with_todo_store(|store| ids.iter().filter_map(|id| store.get(id)).collect());
+    let next_cursor = if has_more {
+        todos.last().map(encode_cursor)
+    } else {
+        None
+    };
+
+    TodosPage {
+        todos,
+        total_count: get_todo_count_by_owner(caller),
+        has_more,
+        next_cursor,
+    }
+}
+
+/// Filtered or re-sorted listing; `total_count` is the number of matches.
+/// A list, tag or due date filter reads its candidates from that index.
+/// Otherwise only the caller's todos inside the created_at bounds are
+/// scanned.
+fn filtered_page(
+    caller: Principal,
+    after: Option<RecencyPosition>,
+    pagination: &PaginationInput,
+    sort: TodoSort,
+) -> TodosPage {
+    let filter = normalize_filter(pagination.filter.clone());
+    let needle = filter
+        .text_contains
+        .as_ref()
+        .map(|text| text.to_lowercase());
+
+    // The list index goes first: the others only hold the caller's own
+    // todos, not those of lists shared with them.
+    let candidates = if let Some(list_id) = filter.list_id {
+        Some(get_todo_ids_by_list(list_id))
+    } else if let Some(tag) = &filter.tag {
+        Some(get_todo_ids_by_tag(caller, tag))
+    } else if filter.due_after.is_some() || filter.due_before.is_some() {
+        Some(get_todo_ids_by_due_date(
+            caller,
+            filter.due_after,
+            filter.due_before,
+        ))
+    } else {
+        None
+    };
+
+    let mut matching = Vec::new();
+    let scanned_by_recency = candidates.is_none();
+    match candidates {
+        Some(ids) => with_todo_store(|store| {
+            for id in ids {
+                if let Some(todo) = store.get(&id) {
+                    // The list index is not keyed by owner, and a shared
+                    // list holds todos of its creator.
+                    if can_read(caller, &todo) && matches_filter(&todo, &filter, needle.as_deref())
+                    {
+                        matching.push(todo);
+                    }
+                }
+            }
+        }),
+        None => {
+            // The index runs newest first, so created_before picks where the
+            // scan starts and created_after where it can stop.
+            let start = filter.created_before.map(|before| (before, TodoId::MAX));
+            scan_todos_by_recency(caller, start, |todo| {
+                if filter
+                    .created_after
+                    .is_some_and(|after| todo.created_at < after)
+                {
+                    return ControlFlow::Break(());
+                }
+                if matches_filter(&todo, &filter, needle.as_deref()) {
+                    matching.push(todo);
+                }
+                ControlFlow::Continue(())
             });
         }
+    }
 
-        let end_index = std::cmp::min(start_index + pagination.limit as usize, all_todos.len());
+    let is_default_sort = sort == TodoSort::default();
+    if !is_default_sort || !scanned_by_recency {
+        sort_todos(&mut matching, sort);
+    }
 
-        let paginated_todos = all_todos[start_index..end_index].to_vec();
-        let has_more = end_index < all_todos.len();
+    let total_count = matching.len() as u64;
+    let cursor_index = match after {
+        Some(position) => matching
+            .iter()
+            .position(|todo| (todo.created_at, todo.id) < position)
+            .unwrap_or(matching.len()),
+        None => 0,
+    };
+    let start_index = std::cmp::min(cursor_index + pagination.offset as usize, matching.len());
+    let end_index = std::cmp::min(start_index + pagination.limit as usize, matching.len());
 
-        TodosResult::Ok(TodosPage {
-            todos: paginated_todos,
-            total_count,
-            has_more,
-        })
-    })
+    let todos = matching[start_index..end_index].to_vec();
+    let has_more = end_index < matching.len();
+    let next_cursor = if has_more && is_default_sort {
+        todos.last().map(encode_cursor)
+    } else {
+        None
+    };
+
+    TodosPage {
+        todos,
+        total_count,
+        has_more,
+        next_cursor,
+    }
+}
+
+fn matches_filter(todo: &Todo, filter: &TodoFilter, needle: Option<&str>) -> bool {
+    filter
+        .completed
+        .is_none_or(|completed| todo.completed == completed)
+        && filter
+            .created_after
+            .is_none_or(|after| todo.created_at >= after)
+        && filter
+            .created_before
+            .is_none_or(|before| todo.created_at <= before)
+        && filter
+            .updated_after
+            .is_none_or(|after| todo.updated_at >= after)
+        && filter
+            .updated_before
+            .is_none_or(|before| todo.updated_at <= before)
+        && needle.is_none_or(|needle| todo.text.to_lowercase().contains(needle))
+        && filter
+            .priority
+            .is_none_or(|priority| todo.priority == Some(priority))
+        && filter
+            .due_after
+            .is_none_or(|after| todo.due_at.is_some_and(|due_at| due_at >= after))
+        && filter
+            .due_before
+            .is_none_or(|before| todo.due_at.is_some_and(|due_at| due_at <= before))
+        && filter
+            .tag
+            .as_ref()
+            .is_none_or(|tag| todo.tags.contains(tag))
+        && filter
+            .list_id
+            .is_none_or(|list_id| todo.list_id == Some(list_id))
+}
+
+/// `filter` with its tag in stored form, or an empty filter.
+fn normalize_filter(filter: Option<TodoFilter>) -> TodoFilter {
+    let mut filter = filter.unwrap_or_default();
+    filter.tag = filter.tag.map(|tag| normalize_tag(&tag));
+    filter
+}
+
+fn sort_todos(todos: &mut [Todo], sort: TodoSort) {
+    todos.sort_by(|a, b| {
+        let ordering = match sort.field {
+            SortField::CreatedAt => a.created_at.cmp(&b.created_at),
+            SortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+            SortField::Id => a.id.cmp(&b.id),
+            SortField::Text => a.text.to_lowercase().cmp(&b.text.to_lowercase()),
+            SortField::Rank => (a.list_id, &a.rank).cmp(&(b.list_id, &b.rank)),
+        }
+        .then(a.id.cmp(&b.id));
+
+        match sort.direction {
+            SortDirection::Asc => ordering,
+            SortDirection::Desc => ordering.reverse(),
+        }
+    });
 }
 
-pub fn update_todo(input: UpdateTodoInput) -> TodoResult {
-    with_todo_store_mut(|store| match store.get(&input.id) {
-        Some(mut todo) => {
-            let mut updated = false;
+/// Todos of `caller` containing a word that starts with every query term,
+/// ranked by how many of their words match, then newest first.
+pub fn search_todos(caller: Principal, query: String, pagination: PaginationInput) -> TodosResult {
+    let terms = match validate_search(&query, &pagination) {
+        Ok(terms) => terms,
+        Err(err) => return TodosResult::Err(err),
+    };
 
-            if let Some(text) = input.text {
-                if let Err(err) = validate_todo_text(&text) {
-                    return TodoResult::Err(err);
+    let mut ranked: Option<BTreeMap<TodoId, u32>> = None;
+    for term in &terms {
+        let hits = get_todo_ids_by_token_prefix(caller, term);
+        let merged = match ranked {
+            None => hits,
+            Some(mut ranked) => {
+                ranked.retain(|id, _| hits.contains_key(id));
+                for (id, count) in ranked.iter_mut() {
+                    *count += hits[id];
                 }
-                todo.text = text.trim().to_string();
-                updated = true;
+                ranked
             }
+        };
+        let done = merged.is_empty();
+        ranked = Some(merged);
+        if done {
+            break;
+        }
+    }
 
-            if let Some(completed) = input.completed {
-                todo.completed = completed;
-                updated = true;
+    let filter = normalize_filter(pagination.filter.clone());
+    let needle = filter
+        .text_contains
+        .as_ref()
+        .map(|text| text.to_lowercase());
+    let mut matching: Vec<(u32, Todo)> = with_todo_store(|store| {
+        ranked
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, count)| store.get(&id).map(|todo| (count, todo)))
+            .filter(|(_, todo)| matches_filter(todo, &filter, needle.as_deref()))
+            .collect()
+    });
+    matching.sort_by(|(a_count, a), (b_count, b)| {
+        b_count
+            .cmp(a_count)
+            .then(b.created_at.cmp(&a.created_at))
+            .then(b.id.cmp(&a.id))
+    });
+
+    let total_count = matching.len() as u64;
+    let start_index = std::cmp::min(pagination.offset as usize, matching.len());
+    let end_index = std::cmp::min(start_index + pagination.limit as usize, matching.len());
+    let todos = matching[start_index..end_index]
+        .iter()
+        .map(|(_, todo)| todo.clone())
+        .collect();
+
+    TodosResult::Ok(TodosPage {
+        todos,
+        total_count,
+        has_more: end_index < matching.len(),
+        next_cursor: None,
+    })
+}
+
+pub fn update_todo(caller: Principal, input: UpdateTodoInput) -> TodoResult {
+    match validate_update_input(&input) {
+        Ok(()) => apply_update(caller, input),
+        Err(err) => TodoResult::Err(err),
+    }
+}
+
+/// Applies every valid update the caller is allowed to make and reports the
+/// rest. All inputs are validated before the first todo is written.
+pub fn update_todos(caller: Principal, inputs: Vec<UpdateTodoInput>) -> TodoBatchResult {
+    if let Err(err) = validate_batch_size(inputs.len()) {
+        return TodoBatchResult::Err(err);
+    }
+
+    let checks: Vec<Result<(), TodoError>> = inputs.iter().map(validate_update_input).collect();
+
+    let results = inputs
+        .into_iter()
+        .zip(checks)
+        .map(|(input, check)| match check {
+            Ok(()) => apply_update(caller, input),
+            Err(err) => TodoResult::Err(err),
+        })
+        .collect();
+    TodoBatchResult::Ok(results)
+}
+
+/// Writes `input` to a todo the caller can edit. `input` must already be
+/// validated.
+fn apply_update(caller: Principal, input: UpdateTodoInput) -> TodoResult {
+    match update_stored_todo(caller, input) {
+        Ok((previous, todo, next_rule)) => {
+            reindex_todo(&previous, &todo);
+            if todo.list_id != previous.list_id {
+                move_subtasks_to_list(&todo);
+            }
+            if let Some(rule) = next_rule {
+                insert_next_occurrence(&todo, rule, time());
             }
+            TodoResult::Ok(todo)
+        }
+        Err(err) => TodoResult::Err(err),
+    }
+}
+
+/// Moves every subtask below `todo` into its list, so a tree is never split
+/// across lists. Subtasks share their parent's owner, so any list the parent
+/// may move to takes them too.
+fn move_subtasks_to_list(todo: &Todo) {
+    let now = time();
+    for id in get_descendant_ids(todo.id) {
+        let previous = match with_todo_store(|store| store.get(&id)) {
+            Some(previous) if previous.list_id != todo.list_id => previous,
+            _ => continue,
+        };
+        let mut subtask = previous.clone();
+        subtask.list_id = todo.list_id;
+        subtask.rank = top_rank(subtask.owner, subtask.list_id);
+        subtask.updated_at = now;
+        subtask.version += 1;
+        with_todo_store_mut(|store| {
+            store.insert(id, subtask.clone());
+        });
+        reindex_todo(&previous, &subtask);
+    }
+}
+
+/// Checks `input` against the stored todo and writes the result. Nothing is
+/// written, including the ranks of a list the todo moves to, unless every
+/// check passes.
+fn update_stored_todo(
+    caller: Principal,
+    input: UpdateTodoInput,
+) -> Result<(Todo, Todo, Option<Recurrence>), TodoError> {
+    let previous = with_todo_store(|store| store.get(&input.id))
+        .ok_or(TodoError::NotFound { id: input.id })?;
+    ensure_can_edit(&previous, caller)?;
+    ensure_unchanged(&previous, input.expected_version)?;
+
+    let mut todo = previous.clone();
+    let mut updated = false;
+
+    if let Some(text) = input.text {
+        todo.text = text.trim().to_string();
+        updated = true;
+    }
+
+    if let Some(completed) = input.completed {
+        todo.completed = completed;
+        updated = true;
+    }
+
+    if let Some(priority) = input.priority {
+        todo.priority = priority;
+        updated = true;
+    }
+
+    if let Some(due_at) = input.due_at {
+        todo.due_at = due_at;
+        updated = true;
+    }
+
+    if let Some(tags) = input.tags {
+        todo.tags = normalize_tags(&tags);
+        updated = true;
+    }
 
-            if updated {
-                todo.updated_at = time();
-                store.insert(input.id, todo.clone());
+    if let Some(list_id) = input.list_id {
+        // A subtask stays in its parent's list; moving the parent, or the
+        // subtask out from under it, moves it along.
+        if todo.parent_id.is_some() && list_id != previous.list_id {
+            return Err(TodoError::validation(
+                "list_id",
+                "must be the list of the parent todo",
+            ));
+        }
+        // A todo only moves between lists of its owner; taking it out of a
+        // list is up to the owner alone.
+        match list_id {
+            Some(list_id) => {
+                let list = validate_list_reference(caller, list_id)?;
+                if list.owner != todo.owner {
+                    return Err(TodoError::validation(
+                        "list_id",
+                        "must belong to the owner of the todo",
+                    ));
+                }
             }
+            None => ensure_owner(&todo, caller)?,
+        }
+        todo.list_id = list_id;
+        updated = true;
+    }
 
-            TodoResult::Ok(todo)
+    if let Some(recurrence) = input.recurrence {
+        todo.recurrence = recurrence;
+        updated = true;
+    }
+
+    if let Some(remind_at) = input.remind_at {
+        todo.remind_at = remind_at;
+        updated = true;
+    }
+
+    if todo.recurrence.is_some() && todo.due_at.is_none() {
+        return Err(TodoError::validation("recurrence", "requires a due date"));
+    }
+    ensure_byte_quota(
+        todo.owner,
+        content_bytes(&previous.text, &previous.tags),
+        content_bytes(&todo.text, &todo.tags),
+    )?;
+    // Completing a recurring todo hands its rule to the next occurrence, so
+    // completing it again does not spawn another.
+    let next_rule = if todo.completed && !previous.completed {
+        todo.recurrence.take()
+    } else {
+        None
+    };
+
+    if updated {
+        // A todo moved to another list goes to the top of it, among its
+        // owner's todos.
+        if todo.list_id != previous.list_id {
+            todo.rank = top_rank(todo.owner, todo.list_id);
         }
-        None => TodoResult::Err("Todo not found".to_string()),
+        todo.updated_at = time();
+        todo.version += 1;
+        with_todo_store_mut(|store| {
+            store.insert(input.id, todo.clone());
+        });
+    }
+
+    Ok((previous, todo, next_rule))
+}
+
+/// Creates the occurrence of a recurring todo after `todo`, carrying `rule`
+/// on. It copies everything but subtasks and the reminder, and starts
+/// incomplete at the top of its list. A series that has fallen behind skips
+/// to its first occurrence after `now`, so an overdue chore is not copied
+/// once for every missed day.
+///
+/// Occurrences count against the owner's quotas like any new todo. An owner
+/// at either quota gets none, which ends the series.
+fn insert_next_occurrence(todo: &Todo, rule: Recurrence, now: u64) -> Option<Todo> {
+    if ensure_todo_quota(todo.owner, content_bytes(&todo.text, &todo.tags)).is_err() {
+        return None;
+    }
+
+    let due_at = todo.due_at.map(|due_at| {
+        let mut next = next_occurrence(due_at, &rule);
+        while next <= now {
+            next = next_occurrence(next, &rule);
+        }
+        next
+    });
+
+    let next = Todo {
+        id: get_next_todo_id(),
+        owner: todo.owner,
+        text: todo.text.clone(),
+        completed: false,
+        created_at: now,
+        updated_at: now,
+        priority: todo.priority,
+        due_at,
+        tags: todo.tags.clone(),
+        list_id: todo.list_id,
+        parent_id: todo.parent_id,
+        rank: top_rank(todo.owner, todo.list_id),
+        recurrence: Some(rule),
+        remind_at: None,
+        version: 0,
+    };
+
+    with_todo_store_mut(|store| {
+        store.insert(next.id, next.clone());
+    });
+    index_todo(&next);
+
+    Some(next)
+}
+
+/// Creates the next occurrence of every recurring todo due within
+/// `RECURRENCE_LOOKAHEAD_NANOS`, so upcoming chores show up before the day
+/// they are due. Called by the recurrence timer; returns how many were
+/// created.
+pub fn materialise_upcoming_occurrences() -> u64 {
+    let now = time();
+    let horizon = now.saturating_add(RECURRENCE_LOOKAHEAD_NANOS);
+    let ids = get_recurring_ids_due_by(horizon, MAX_OCCURRENCES_PER_RUN);
+
+    let mut created = 0;
+    for id in ids {
+        let previous = match with_todo_store(|store| store.get(&id)) {
+            Some(previous) => previous,
+            None => continue,
+        };
+        let mut todo = previous.clone();
+        let rule = match todo.recurrence.take() {
+            Some(rule) => rule,
+            None => continue,
+        };
+        // Handing the rule on is bookkeeping rather than an edit, so
+        // `updated_at` is left unchanged.
+        with_todo_store_mut(|store| {
+            store.insert(id, todo.clone());
+        });
+        reindex_todo(&previous, &todo);
+        if insert_next_occurrence(&todo, rule, now).is_some() {
+            created += 1;
+        }
+    }
+    created
+}
+
+/// Moves every reminder that has come due into its owner's inbox and clears
+/// it from the todo. Reminders on completed todos are dropped. Called by the
+/// reminder timer; returns how many notifications were delivered.
+pub fn deliver_due_reminders() -> u64 {
+    let now = time();
+    let ids = get_due_reminder_ids(now, MAX_REMINDERS_PER_RUN);
+
+    let mut delivered = 0;
+    for id in ids {
+        let previous = match with_todo_store(|store| store.get(&id)) {
+            Some(previous) => previous,
+            None => continue,
+        };
+        let mut todo = previous.clone();
+        let remind_at = match todo.remind_at.take() {
+            Some(remind_at) => remind_at,
+            None => continue,
+        };
+        // Delivery is bookkeeping rather than an edit, so `updated_at` is
+        // left unchanged.
+        with_todo_store_mut(|store| {
+            store.insert(id, todo.clone());
+        });
+        reindex_todo(&previous, &todo);
+
+        if !todo.completed {
+            deliver_notification(&todo, remind_at, now);
+            delivered += 1;
+        }
+    }
+    delivered
+}
+
+/// Adds a notification to the owner's inbox, dropping the oldest ones once it
+/// holds `MAX_NOTIFICATIONS_PER_OWNER`.
+fn deliver_notification(todo: &Todo, remind_at: u64, now: u64) {
+    let inbox = get_notifications_by_owner(todo.owner);
+    let overflow = (inbox.len() + 1).saturating_sub(MAX_NOTIFICATIONS_PER_OWNER);
+    for notification in inbox.iter().take(overflow) {
+        remove_notification(todo.owner, notification.id);
+    }
+
+    insert_notification(Notification {
+        id: get_next_notification_id(),
+        owner: todo.owner,
+        todo_id: todo.id,
+        text: todo.text.clone(),
+        remind_at,
+        delivered_at: now,
+    });
+}
+
+/// The caller's unacknowledged notifications, oldest first.
+pub fn get_notifications(caller: Principal) -> Vec<Notification> {
+    get_notifications_by_owner(caller)
+}
+
+/// Removes the given notifications from the caller's inbox and returns how
+/// many were removed. Ids that are unknown or belong to someone else are
+/// ignored.
+pub fn ack_notifications(caller: Principal, ids: Vec<NotificationId>) -> u64 {
+    ids.into_iter()
+        .filter(|id| remove_notification(caller, *id).is_some())
+        .count() as u64
+}
+
+/// Places a todo directly below `before` and above `after` in its list,
+/// rewriting only its own rank. Either neighbour may be `None` at an end of
+/// the list. Reordering is not an edit, so `updated_at` is left unchanged.
+pub fn move_todo(
+    caller: Principal,
+    id: TodoId,
+    before: Option<TodoId>,
+    after: Option<TodoId>,
+) -> TodoResult {
+    match reorder(caller, id, before, after) {
+        Ok(todo) => TodoResult::Ok(todo),
+        Err(err) => TodoResult::Err(err),
+    }
+}
+
+fn reorder(
+    caller: Principal,
+    id: TodoId,
+    before: Option<TodoId>,
+    after: Option<TodoId>,
+) -> Result<Todo, TodoError> {
+    let moved = with_todo_store(|store| store.get(&id)).ok_or(TodoError::NotFound { id })?;
+    ensure_can_edit(&moved, caller)?;
+    if before.is_none() && after.is_none() {
+        return Err(TodoError::validation(
+            "before",
+            "either before or after is required",
+        ));
+    }
+
+    let place = || -> Result<String, TodoError> {
+        let low = neighbour_rank("before", before, &moved)?;
+        let high = neighbour_rank("after", after, &moved)?;
+        if let (Some(low), Some(high)) = (&low, &high) {
+            if low >= high {
+                return Err(TodoError::validation(
+                    "after",
+                    "must come later in the list than before",
+                ));
+            }
+        }
+        Ok(rank_between(low.as_deref(), high.as_deref()))
+    };
+    let mut rank = place()?;
+    if rank.len() > MAX_RANK_LENGTH {
+        rebalance_ranks(moved.owner, moved.list_id);
+        rank = place()?;
+    }
+
+    // Respacing may have rewritten the stored copy.
+    let previous = with_todo_store(|store| store.get(&id)).ok_or(TodoError::NotFound { id })?;
+    let mut todo = previous.clone();
+    todo.rank = rank;
+    with_todo_store_mut(|store| {
+        store.insert(id, todo.clone());
+    });
+    reindex_todo(&previous, &todo);
+
+    Ok(todo)
+}
+
+/// Rank of the neighbour `field` names, which must be another todo in the
+/// same list as `moved`.
+fn neighbour_rank(
+    field: &str,
+    neighbour_id: Option<TodoId>,
+    moved: &Todo,
+) -> Result<Option<String>, TodoError> {
+    let neighbour_id = match neighbour_id {
+        Some(neighbour_id) => neighbour_id,
+        None => return Ok(None),
+    };
+    match with_todo_store(|store| store.get(&neighbour_id)) {
+        Some(neighbour)
+            if neighbour.id != moved.id
+                && neighbour.owner == moved.owner
+                && neighbour.list_id == moved.list_id =>
+        {
+            Ok(Some(neighbour.rank))
+        }
+        _ => Err(TodoError::validation(
+            field,
+            "must be another todo in the same list",
+        )),
+    }
+}
+
+/// Rank that puts a todo at the top of `owner`'s `list_id`.
+fn top_rank(owner: Principal, list_id: Option<ListId>) -> String {
+    let rank = rank_between(None, get_first_rank(owner, list_id).as_deref());
+    if rank.len() <= MAX_RANK_LENGTH {
+        return rank;
+    }
+    rebalance_ranks(owner, list_id);
+    rank_between(None, get_first_rank(owner, list_id).as_deref())
+}
+
+/// Respaces the ranks of `owner`'s `list_id` evenly, keeping their order.
+/// Only needed once ranks have grown to `MAX_RANK_LENGTH`.
+fn rebalance_ranks(owner: Principal, list_id: Option<ListId>) {
+    let ids = get_todo_ids_by_rank(owner, list_id);
+    let ranks = evenly_spaced_ranks(ids.len());
+    for (id, rank) in ids.into_iter().zip(ranks) {
+        let respaced = with_todo_store_mut(|store| {
+            let previous = store.get(&id)?;
+            let mut todo = previous.clone();
+            todo.rank = rank;
+            store.insert(id, todo.clone());
+            Some((previous, todo))
+        });
+        if let Some((previous, todo)) = respaced {
+            reindex_todo(&previous, &todo);
+        }
+    }
+}
+
+/// Moves a todo and every subtask below it to the trash.
+pub fn delete_todo_by_id(caller: Principal, id: TodoId) -> DeleteResult {
+    let removed: Result<Todo, TodoError> = with_todo_store_mut(|store| match store.get(&id) {
+        Some(todo) => {
+            ensure_can_edit(&todo, caller)?;
+            store.remove(&id);
+            Ok(todo)
+        }
+        None => Err(TodoError::NotFound { id }),
+    });
+
+    match removed {
+        Ok(todo) => {
+            let deleted_at = time();
+            let subtasks: Vec<Todo> = with_todo_store_mut(|store| {
+                get_descendant_ids(id)
+                    .iter()
+                    .filter_map(|id| store.remove(id))
+                    .collect()
+            });
+            for todo in std::iter::once(todo).chain(subtasks) {
+                unindex_todo(&todo);
+                insert_trashed_todo(TrashedTodo { todo, deleted_at });
+            }
+            DeleteResult::Ok(true)
+        }
+        Err(err) => DeleteResult::Err(err),
+    }
+}
+
+pub fn delete_todos(caller: Principal, ids: Vec<TodoId>) -> DeleteBatchResult {
+    if let Err(err) = validate_batch_size(ids.len()) {
+        return DeleteBatchResult::Err(err);
+    }
+
+    let results = ids
+        .into_iter()
+        .map(|id| delete_todo_by_id(caller, id))
+        .collect();
+    DeleteBatchResult::Ok(results)
+}
+
+/// The caller's trash, most recently deleted first. Only `offset` and
+/// `limit` apply.
+pub fn list_trash(caller: Principal, pagination: PaginationInput) -> TrashResult {
+    if let Err(err) = validate_pagination(&pagination) {
+        return TrashResult::Err(err);
+    }
+    if pagination.cursor.is_some() || pagination.filter.is_some() || pagination.sort.is_some() {
+        return TrashResult::Err(TodoError::validation(
+            "pagination",
+            "only offset and limit are supported for the trash",
+        ));
+    }
+
+    let mut ids = get_trashed_ids_by_owner(caller, pagination.offset, pagination.limit + 1);
+    let has_more = ids.len() as u64 > pagination.limit;
+    ids.truncate(pagination.limit as usize);
+
+    TrashResult::Ok(TrashPage {
+        items: ids.into_iter().filter_map(get_trashed_todo).collect(),
+        total_count: get_trash_count_by_owner(caller),
+        has_more,
     })
 }
 
-pub fn delete_todo_by_id(id: TodoId) -> DeleteResult {
-    with_todo_store_mut(|store| match store.remove(&id) {
-        Some(_) => DeleteResult::Ok(true),
-        None => DeleteResult::Err("Todo not found".to_string()),
+/// Moves a todo from the trash back into the caller's list, with the
+/// subtasks that were deleted along with it.
+pub fn restore_todo(caller: Principal, id: TodoId) -> TodoResult {
+    let trashed = match get_trashed_todo(id) {
+        Some(trashed) => trashed,
+        None => return TodoResult::Err(TodoError::NotFound { id }),
+    };
+    if let Err(err) = ensure_owner(&trashed.todo, caller) {
+        return TodoResult::Err(err);
+    }
+    let (mut subtasks, height) = trashed_subtasks(&trashed);
+    // Restored todos are live again, so they count against the quotas.
+    let bytes = std::iter::once(&trashed.todo)
+        .chain(&subtasks)
+        .map(|todo| content_bytes(&todo.text, &todo.tags))
+        .sum();
+    if let Err(err) = ensure_todos_quota(caller, 1 + subtasks.len() as u64, bytes) {
+        return TodoResult::Err(err);
+    }
+
+    let now = time();
+    let mut todo = trashed.todo;
+    // Its list may have been deleted while the todo was in the trash.
+    if todo.list_id.and_then(get_list).is_none() {
+        todo.list_id = None;
+    }
+    // A subtask goes back under its parent, and into the parent's list, if
+    // the parent is live; otherwise it comes back at the top level.
+    match todo
+        .parent_id
+        .map(|parent_id| validate_parent(caller, parent_id, height))
+    {
+        Some(Ok(parent)) => todo.list_id = parent.list_id,
+        Some(Err(_)) => todo.parent_id = None,
+        None => {}
+    }
+
+    let list_id = todo.list_id;
+    for restored in std::iter::once(&mut todo).chain(&mut subtasks) {
+        remove_trashed_todo(restored.id);
+        restored.list_id = list_id;
+        restored.updated_at = now;
+        restored.version += 1;
+        restored.rank = top_rank(caller, list_id);
+        with_todo_store_mut(|store| {
+            store.insert(restored.id, restored.clone());
+        });
+        index_todo(restored);
+    }
+
+    TodoResult::Ok(todo)
+}
+
+/// The subtasks trashed along with `trashed`, level by level, and how many
+/// levels they span. A subtree is trashed in one call, so its subtasks share
+/// the `deleted_at` of its root.
+fn trashed_subtasks(trashed: &TrashedTodo) -> (Vec<Todo>, usize) {
+    let mut children: BTreeMap<TodoId, Vec<Todo>> = BTreeMap::new();
+    for id in get_trashed_ids_by_owner(trashed.todo.owner, 0, u64::MAX) {
+        match get_trashed_todo(id) {
+            Some(item) if item.deleted_at == trashed.deleted_at => {
+                if let Some(parent_id) = item.todo.parent_id {
+                    children.entry(parent_id).or_default().push(item.todo);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut subtasks = Vec::new();
+    let mut height = 0;
+    let mut level = vec![trashed.todo.id];
+    loop {
+        let next: Vec<Todo> = level
+            .iter()
+            .flat_map(|id| children.remove(id).unwrap_or_default())
+            .collect();
+        if next.is_empty() {
+            return (subtasks, height);
+        }
+        height += 1;
+        level = next.iter().map(|todo| todo.id).collect();
+        subtasks.extend(next);
+    }
+}
+
+/// Permanently removes up to `MAX_PURGE_PER_RUN` todos from the caller's
+/// trash and returns how many were removed. A larger trash takes several
+/// calls; it is empty once a call returns 0.
+pub fn empty_trash(caller: Principal) -> u64 {
+    let ids = get_trashed_ids_by_owner(caller, 0, MAX_PURGE_PER_RUN);
+    for id in &ids {
+        remove_trashed_todo(*id);
+    }
+    ids.len() as u64
+}
+
+/// Permanently removes todos whose retention has run out. Called by the
+/// purge timer; returns how many were removed.
+pub fn purge_expired_trash() -> u64 {
+    let cutoff = time().saturating_sub(get_config().trash_retention_nanos);
+    let ids = get_expired_trash_ids(cutoff, MAX_PURGE_PER_RUN);
+    for id in &ids {
+        remove_trashed_todo(*id);
+    }
+    ids.len() as u64
+}
+
+pub fn create_list(caller: Principal, input: CreateListInput) -> ListResult {
+    let check = validate_list_name(&input.name).and_then(|()| match &input.colour {
+        Some(colour) => validate_list_colour(colour),
+        None => Ok(()),
+    });
+    if let Err(err) = check {
+        return ListResult::Err(err);
+    }
+
+    let list = TodoList {
+        id: get_next_list_id(),
+        owner: caller,
+        name: input.name.trim().to_string(),
+        colour: input
+            .colour
+            .map_or_else(|| DEFAULT_LIST_COLOUR.to_string(), |c| c.to_lowercase()),
+        archived: false,
+        created_at: time(),
+    };
+    insert_list(list.clone());
+
+    ListResult::Ok(list)
+}
+
+/// The caller's lists, oldest first.
+pub fn get_lists(caller: Principal, include_archived: bool) -> Vec<TodoList> {
+    get_lists_by_owner(caller)
+        .into_iter()
+        .filter(|list| include_archived || !list.archived)
+        .collect()
+}
+
+pub fn update_list(caller: Principal, input: UpdateListInput) -> ListResult {
+    let mut list = match owned_list(caller, input.id) {
+        Ok(list) => list,
+        Err(err) => return ListResult::Err(err),
+    };
+
+    if let Some(name) = input.name {
+        if let Err(err) = validate_list_name(&name) {
+            return ListResult::Err(err);
+        }
+        list.name = name.trim().to_string();
+    }
+    if let Some(colour) = input.colour {
+        if let Err(err) = validate_list_colour(&colour) {
+            return ListResult::Err(err);
+        }
+        list.colour = colour.to_lowercase();
+    }
+    if let Some(archived) = input.archived {
+        list.archived = archived;
+    }
+    insert_list(list.clone());
+
+    ListResult::Ok(list)
+}
+
+/// Deletes a list, moves its todos to its creator's trash and stops sharing
+/// it. Todos restored later come back without a list.
+pub fn delete_list(caller: Principal, id: ListId) -> DeleteResult {
+    if let Err(err) = owned_list(caller, id) {
+        return DeleteResult::Err(err);
+    }
+
+    for todo_id in get_todo_ids_by_list(id) {
+        // The caller owns the list, so they can delete everything in it.
+        let _ = delete_todo_by_id(caller, todo_id);
+    }
+    for (principal, _) in get_shares_by_list(id) {
+        remove_share(id, principal);
+    }
+    remove_list(id);
+
+    DeleteResult::Ok(true)
+}
+
+/// Gives `principal` `role` on a list, replacing any role they had. Takes
+/// the owner role on the list.
+pub fn share_list(
+    caller: Principal,
+    list_id: ListId,
+    principal: Principal,
+    role: ListRole,
+) -> ShareResult {
+    let list = match owned_list(caller, list_id) {
+        Ok(list) => list,
+        Err(err) => return ShareResult::Err(err),
+    };
+    if principal == list.owner {
+        return ShareResult::Err(TodoError::validation("principal", "already owns the list"));
+    }
+    if principal == Principal::anonymous() {
+        return ShareResult::Err(TodoError::validation(
+            "principal",
+            "cannot be the anonymous principal",
+        ));
+    }
+
+    insert_share(list_id, principal, role);
+    ShareResult::Ok(ListShare {
+        list_id,
+        principal,
+        role,
     })
 }
 
-pub fn get_todo_count() -> u64 {
-    with_todo_store(|store| store.len())
+/// Stops sharing a list with `principal`, effective from their next call.
+/// Owners can revoke anyone's share and anyone can give up their own.
+/// Returns whether `principal` had a share.
+pub fn revoke_share(caller: Principal, list_id: ListId, principal: Principal) -> DeleteResult {
+    let check = if principal == caller {
+        get_list(list_id)
+            .map(|_| ())
+            .ok_or(TodoError::NotFound { id: list_id })
+    } else {
+        owned_list(caller, list_id).map(|_| ())
+    };
+    match check {
+        Ok(()) => DeleteResult::Ok(remove_share(list_id, principal).is_some()),
+        Err(err) => DeleteResult::Err(err),
+    }
+}
+
+/// Everyone with access to a list, its creator first. Visible to anyone the
+/// list is shared with.
+pub fn get_list_shares(caller: Principal, list_id: ListId) -> SharesResult {
+    let list = match get_list(list_id) {
+        Some(list) if list_role(caller, &list).is_some() => list,
+        _ => return SharesResult::Err(TodoError::NotFound { id: list_id }),
+    };
+
+    let creator = ListShare {
+        list_id,
+        principal: list.owner,
+        role: ListRole::Owner,
+    };
+    let shares = get_shares_by_list(list_id)
+        .into_iter()
+        .map(|(principal, role)| ListShare {
+            list_id,
+            principal,
+            role,
+        });
+    SharesResult::Ok(std::iter::once(creator).chain(shares).collect())
+}
+
+/// Lists other principals have shared with the caller, archived ones
+/// included, in id order.
+pub fn list_shared_with_me(caller: Principal) -> Vec<SharedList> {
+    get_list_ids_shared_with(caller)
+        .into_iter()
+        .filter_map(|list_id| {
+            let list = get_list(list_id)?;
+            let role = get_share(list_id, caller)?;
+            Some(SharedList { list, role })
+        })
+        .collect()
+}
+
+pub fn get_todo_count(caller: Principal) -> u64 {
+    get_todo_count_by_owner(caller)
+}
+
+/// Makes `principal` an admin; returns whether they were not one already.
+/// The anonymous principal is never made an admin.
+pub fn grant_admin(principal: Principal) -> bool {
+    principal != Principal::anonymous() && insert_admin(principal)
+}
+
+/// Applies the argument of `init` or `post_upgrade`. An invalid config or an
+/// anonymous `legacy_owner` fails the install or upgrade.
+pub fn apply_init_args(args: InitArgs) {
+    if let Some(owner) = args.legacy_owner {
+        if owner == Principal::anonymous() {
+            ic_cdk::trap("Invalid legacy_owner: cannot be the anonymous principal");
+        }
+        adopt_anonymous_data(owner);
+    }
+    for admin in args.admins {
+        grant_admin(admin);
+    }
+    if let Some(config) = args.config {
+        if let Err(err) = validate_config(&config) {
+            ic_cdk::trap(&format!("Invalid config: {}", err));
+        }
+        set_config(config);
+    }
+}
+
+/// Hands the live todos and the lists of the anonymous principal to `owner`
+/// and returns how many todos moved. Trashed todos and notifications are left
+/// to expire.
+pub fn adopt_anonymous_data(owner: Principal) -> u64 {
+    let anonymous = Principal::anonymous();
+    for list in get_lists_by_owner(anonymous) {
+        remove_list(list.id);
+        insert_list(TodoList { owner, ..list });
+    }
+
+    let ids = get_todo_ids_by_recency(anonymous, None, 0, u64::MAX);
+    let mut list_ids: Vec<Option<ListId>> = ids
+        .iter()
+        .filter_map(|id| with_todo_store(|store| store.get(id)))
+        .map(|todo| todo.list_id)
+        .collect();
+    list_ids.sort();
+    list_ids.dedup();
+
+    // Ranks only order one owner's todos, so every list the adopted todos
+    // are in is respaced with them after the owner's own, each group
+    // keeping its order.
+    let mut ranks = BTreeMap::new();
+    for list_id in list_ids {
+        let mut ordered = get_todo_ids_by_rank(owner, list_id);
+        ordered.extend(get_todo_ids_by_rank(anonymous, list_id));
+        let respaced = evenly_spaced_ranks(ordered.len());
+        ranks.extend(ordered.into_iter().zip(respaced));
+    }
+
+    for (id, rank) in ranks {
+        if let Some(previous) = with_todo_store(|store| store.get(&id)) {
+            let todo = Todo {
+                owner,
+                rank,
+                ..previous.clone()
+            };
+            unindex_todo(&previous);
+            with_todo_store_mut(|store| {
+                store.insert(id, todo.clone());
+            });
+            index_todo(&todo);
+        }
+    }
+    ids.len() as u64
+}
+
+/// Replaces the canister's config. Todos already stored are kept even if
+/// they no longer fit the new limits.
+pub fn update_config(config: Config) -> ConfigResult {
+    match validate_config(&config) {
+        Ok(()) => {
+            set_config(config.clone());
+            ConfigResult::Ok(config)
+        }
+        Err(err) => ConfigResult::Err(err),
+    }
+}
+
+/// Takes `principal` out of the admin set; returns whether they were in it.
+/// Controllers stay admins regardless.
+pub fn revoke_admin(principal: Principal) -> bool {
+    remove_admin(principal)
+}
+
+/// The admin set, in principal order. Controllers are not listed.
+pub fn list_admins() -> Vec<Principal> {
+    get_admins()
+}
+
+/// Refuses every further call from `principal`; their data is kept. Admins
+/// cannot be banned. Returns whether the principal was newly banned.
+pub fn ban_principal(principal: Principal) -> bool {
+    !is_admin_principal(principal) && insert_ban(principal, time())
+}
+
+/// Lifts a ban; returns whether `principal` was banned.
+pub fn unban_principal(principal: Principal) -> bool {
+    remove_ban(principal)
+}
+
+pub fn list_banned() -> Vec<Principal> {
+    get_banned()
+}
+
+pub fn get_stats() -> GlobalStats {
+    GlobalStats {
+        cycles: ic_cdk::api::canister_balance128(),
+        ..get_global_stats()
+    }
+}
+
+/// Runs one timer job now; returns how many items it handled.
+pub fn run_maintenance(task: MaintenanceTask) -> u64 {
+    match task {
+        MaintenanceTask::PurgeExpiredTrash => purge_expired_trash(),
+        MaintenanceTask::MaterialiseRecurringTodos => materialise_upcoming_occurrences(),
+        MaintenanceTask::DeliverReminders => deliver_due_reminders(),
+    }
 }
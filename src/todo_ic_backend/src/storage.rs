@@ -1,14 +1,232 @@
+use candid::Principal;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
+use ic_stable_structures::storable::Bound as StorableBound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::{Bound, ControlFlow};
 
-use crate::types::{Todo, TodoId};
+use crate::search::{tokenize, MAX_TOKEN_LENGTH};
+use crate::types::recurrence::next_occurrence;
+use crate::types::{
+    Config, GlobalStats, ListId, ListRole, Notification, NotificationId, Todo, TodoId, TodoList,
+    TrashedTodo, MAX_RANK_LENGTH, MAX_TAG_LENGTH,
+};
 
 pub type Memory = VirtualMemory<DefaultMemoryImpl>;
 pub type TodoStorage = StableBTreeMap<TodoId, Todo, Memory>;
 pub type IdStorage = StableBTreeMap<u8, TodoId, Memory>;
+/// Per-owner recency index keyed by `(owner, (!created_at, !id))`. Both
+/// numbers are stored bit-inverted so ascending key order is newest first and
+/// a page is a forward range scan.
+pub type CreatedIndex = StableBTreeMap<(Principal, (u64, TodoId)), (), Memory>;
+pub type OwnerCountStorage = StableBTreeMap<Principal, u64, Memory>;
+/// Bytes of text and tags in each owner's live todos.
+pub type OwnerBytesStorage = StableBTreeMap<Principal, u64, Memory>;
+pub type SchemaVersionCell = StableCell<u32, Memory>;
+/// Inverted index with one entry per distinct token of each todo. Entries of
+/// one owner sharing a token prefix are contiguous, so a prefix lookup is a
+/// single range scan.
+pub type SearchIndex = StableBTreeMap<TokenKey, (), Memory>;
+pub type ConfigCell = StableCell<Config, Memory>;
+/// Per-owner due date index keyed by `(owner, (due_at, id))`, soonest first.
+/// Only todos with a due date have an entry.
+pub type DueIndex = StableBTreeMap<(Principal, (u64, TodoId)), (), Memory>;
+/// One entry per tag of each todo, keyed like the search index.
+pub type TagIndex = StableBTreeMap<TokenKey, (), Memory>;
+pub type ListStorage = StableBTreeMap<ListId, TodoList, Memory>;
+pub type OwnerListIndex = StableBTreeMap<(Principal, ListId), (), Memory>;
+/// Members of each list keyed by `(list_id, id)`.
+pub type ListTodoIndex = StableBTreeMap<(ListId, TodoId), (), Memory>;
+/// Subtasks of each todo keyed by `(parent_id, id)`.
+pub type ChildIndex = StableBTreeMap<(TodoId, TodoId), (), Memory>;
+/// Manual order of each owner's lists; the todos of one list, or of no list,
+/// are contiguous and in rank order.
+pub type RankIndex = StableBTreeMap<RankKey, (), Memory>;
+/// Recurring todos keyed by `(next_due_at, id)`, where `next_due_at` is when
+/// their next occurrence is due, soonest first.
+pub type RecurrenceIndex = StableBTreeMap<(u64, TodoId), (), Memory>;
+/// Pending reminders keyed by `(remind_at, id)`, soonest first.
+pub type ReminderIndex = StableBTreeMap<(u64, TodoId), (), Memory>;
+/// Per-owner notification inboxes keyed by `(owner, id)`; ids grow with each
+/// delivery, so an inbox is in delivery order.
+pub type NotificationStorage = StableBTreeMap<(Principal, NotificationId), Notification, Memory>;
+/// Access control list of shared lists keyed by `(list_id, principal)`. A
+/// list's creator has no entry; they always own it.
+pub type ListAclStorage = StableBTreeMap<(ListId, Principal), ListRole, Memory>;
+/// The same shares keyed by `(principal, list_id)`, so the lists shared with
+/// someone are a range scan.
+pub type SharedWithIndex = StableBTreeMap<(Principal, ListId), (), Memory>;
+/// Principals made admins through `init` or `add_admin`. Controllers are
+/// admins without an entry.
+pub type AdminStorage = StableBTreeMap<Principal, (), Memory>;
+/// Banned principals and when they were banned.
+pub type BanStorage = StableBTreeMap<Principal, u64, Memory>;
+pub type TrashStorage = StableBTreeMap<TodoId, TrashedTodo, Memory>;
+/// Per-owner trash listing keyed by `(owner, (!deleted_at, !id))`, most
+/// recently deleted first.
+pub type TrashOwnerIndex = StableBTreeMap<(Principal, (u64, TodoId)), (), Memory>;
+/// Trashed todos keyed by `(deleted_at, id)`, so the purge job reads only
+/// entries past their retention.
+pub type TrashExpiryIndex = StableBTreeMap<(u64, TodoId), (), Memory>;
+
+/// Position of a todo in its owner's newest-first listing.
+pub type RecencyPosition = (u64, TodoId);
 
 const TODO_COUNTER_KEY: u8 = 0;
+const LIST_COUNTER_KEY: u8 = 1;
+const NOTIFICATION_COUNTER_KEY: u8 = 2;
+
+// Virtual memory layout. Ids are never reused: a migration that replaces a
+// structure moves its data into a fresh id and leaves the old one untouched.
+pub const TODO_MEMORY_ID: MemoryId = MemoryId::new(0);
+pub const ID_MEMORY_ID: MemoryId = MemoryId::new(1);
+// MemoryId 2 held the (owner, id) index that schema v2 replaced with CREATED_INDEX.
+pub const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const CREATED_INDEX_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const OWNER_COUNT_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const SEARCH_INDEX_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const TRASH_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const TRASH_OWNER_INDEX_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const TRASH_EXPIRY_INDEX_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const DUE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const TAG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const LIST_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const OWNER_LIST_INDEX_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub const LIST_TODO_INDEX_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const CHILD_INDEX_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const RANK_INDEX_MEMORY_ID: MemoryId = MemoryId::new(17);
+pub const RECURRENCE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(18);
+pub const REMINDER_INDEX_MEMORY_ID: MemoryId = MemoryId::new(19);
+pub const NOTIFICATION_MEMORY_ID: MemoryId = MemoryId::new(20);
+pub const LIST_ACL_MEMORY_ID: MemoryId = MemoryId::new(21);
+pub const SHARED_WITH_INDEX_MEMORY_ID: MemoryId = MemoryId::new(22);
+pub const ADMIN_MEMORY_ID: MemoryId = MemoryId::new(23);
+pub const BAN_MEMORY_ID: MemoryId = MemoryId::new(24);
+pub const OWNER_BYTES_MEMORY_ID: MemoryId = MemoryId::new(25);
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Largest principal in bytes, as defined by the IC interface spec.
+const MAX_PRINCIPAL_LENGTH: usize = 29;
+
+// Tags share `TokenKey`, whose bound is sized for search tokens.
+const _: () = assert!(MAX_TAG_LENGTH <= MAX_TOKEN_LENGTH);
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TokenKey {
+    pub owner: Principal,
+    pub token: String,
+    pub id: TodoId,
+}
+
+// Length-prefixed owner and token followed by the big-endian id. The map
+// orders keys by the derived `Ord`, not by these bytes.
+impl Storable for TokenKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let owner = self.owner.as_slice();
+        let mut bytes = Vec::with_capacity(2 + owner.len() + self.token.len() + 8);
+        bytes.push(owner.len() as u8);
+        bytes.extend_from_slice(owner);
+        bytes.push(self.token.len() as u8);
+        bytes.extend_from_slice(self.token.as_bytes());
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let owner_end = 1 + bytes[0] as usize;
+        let owner = Principal::from_slice(&bytes[1..owner_end]);
+        let token_end = owner_end + 1 + bytes[owner_end] as usize;
+        let token = String::from_utf8(bytes[owner_end + 1..token_end].to_vec())
+            .expect("failed to decode search token");
+        let id = TodoId::from_be_bytes(
+            bytes[token_end..]
+                .try_into()
+                .expect("failed to decode search token id"),
+        );
+        TokenKey { owner, token, id }
+    }
+
+    const BOUND: StorableBound = StorableBound::Bounded {
+        max_size: (2 + MAX_PRINCIPAL_LENGTH + MAX_TOKEN_LENGTH + 8) as u32,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RankKey {
+    pub owner: Principal,
+    pub list_id: Option<ListId>,
+    pub rank: String,
+    pub id: TodoId,
+}
+
+impl RankKey {
+    /// The first key of the `(owner, list_id)` scope.
+    fn scope_start(owner: Principal, list_id: Option<ListId>) -> Self {
+        RankKey {
+            owner,
+            list_id,
+            rank: String::new(),
+            id: TodoId::MIN,
+        }
+    }
+
+    fn in_scope(&self, owner: Principal, list_id: Option<ListId>) -> bool {
+        self.owner == owner && self.list_id == list_id
+    }
+}
+
+// Length-prefixed owner, a presence byte and the big-endian list id (zero
+// when absent), the length-prefixed rank and the big-endian id.
+impl Storable for RankKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let owner = self.owner.as_slice();
+        let mut bytes = Vec::with_capacity(2 + owner.len() + 9 + self.rank.len() + 8);
+        bytes.push(owner.len() as u8);
+        bytes.extend_from_slice(owner);
+        bytes.push(self.list_id.is_some() as u8);
+        bytes.extend_from_slice(&self.list_id.unwrap_or(0).to_be_bytes());
+        bytes.push(self.rank.len() as u8);
+        bytes.extend_from_slice(self.rank.as_bytes());
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let owner_end = 1 + bytes[0] as usize;
+        let owner = Principal::from_slice(&bytes[1..owner_end]);
+        let list_id = ListId::from_be_bytes(
+            bytes[owner_end + 1..owner_end + 9]
+                .try_into()
+                .expect("failed to decode rank list id"),
+        );
+        let list_id = (bytes[owner_end] == 1).then_some(list_id);
+        let rank_start = owner_end + 10;
+        let rank_end = rank_start + bytes[owner_end + 9] as usize;
+        let rank =
+            String::from_utf8(bytes[rank_start..rank_end].to_vec()).expect("failed to decode rank");
+        let id = TodoId::from_be_bytes(
+            bytes[rank_end..]
+                .try_into()
+                .expect("failed to decode rank todo id"),
+        );
+        RankKey {
+            owner,
+            list_id,
+            rank,
+            id,
+        }
+    }
+
+    const BOUND: StorableBound = StorableBound::Bounded {
+        max_size: (2 + MAX_PRINCIPAL_LENGTH + 9 + MAX_RANK_LENGTH + 8) as u32,
+        is_fixed_size: false,
+    };
+}
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -16,15 +234,155 @@ thread_local! {
 
     static TODO_STORE: RefCell<TodoStorage> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))),
+            get_memory(TODO_MEMORY_ID),
         )
     );
 
     static ID_STORE: RefCell<IdStorage> = RefCell::new(
         StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))),
+            get_memory(ID_MEMORY_ID),
+        )
+    );
+
+    static CREATED_INDEX: RefCell<CreatedIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(CREATED_INDEX_MEMORY_ID),
+        )
+    );
+
+    static OWNER_COUNTS: RefCell<OwnerCountStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(OWNER_COUNT_MEMORY_ID),
+        )
+    );
+
+    static OWNER_BYTES: RefCell<OwnerBytesStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(OWNER_BYTES_MEMORY_ID),
+        )
+    );
+
+    static SEARCH_INDEX: RefCell<SearchIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(SEARCH_INDEX_MEMORY_ID),
+        )
+    );
+
+    static DUE_INDEX: RefCell<DueIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(DUE_INDEX_MEMORY_ID),
+        )
+    );
+
+    static TAG_INDEX: RefCell<TagIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(TAG_INDEX_MEMORY_ID),
+        )
+    );
+
+    static LIST_STORE: RefCell<ListStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(LIST_MEMORY_ID),
+        )
+    );
+
+    static OWNER_LIST_INDEX: RefCell<OwnerListIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(OWNER_LIST_INDEX_MEMORY_ID),
+        )
+    );
+
+    static LIST_TODO_INDEX: RefCell<ListTodoIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(LIST_TODO_INDEX_MEMORY_ID),
+        )
+    );
+
+    static CHILD_INDEX: RefCell<ChildIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(CHILD_INDEX_MEMORY_ID),
+        )
+    );
+
+    static RANK_INDEX: RefCell<RankIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(RANK_INDEX_MEMORY_ID),
+        )
+    );
+
+    static RECURRENCE_INDEX: RefCell<RecurrenceIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(RECURRENCE_INDEX_MEMORY_ID),
+        )
+    );
+
+    static REMINDER_INDEX: RefCell<ReminderIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(REMINDER_INDEX_MEMORY_ID),
+        )
+    );
+
+    static NOTIFICATION_STORE: RefCell<NotificationStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(NOTIFICATION_MEMORY_ID),
+        )
+    );
+
+    static LIST_ACL: RefCell<ListAclStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(LIST_ACL_MEMORY_ID),
+        )
+    );
+
+    static SHARED_WITH_INDEX: RefCell<SharedWithIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(SHARED_WITH_INDEX_MEMORY_ID),
+        )
+    );
+
+    static ADMINS: RefCell<AdminStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(ADMIN_MEMORY_ID),
+        )
+    );
+
+    static BANNED: RefCell<BanStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(BAN_MEMORY_ID),
+        )
+    );
+
+    static TRASH_STORE: RefCell<TrashStorage> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(TRASH_MEMORY_ID),
+        )
+    );
+
+    static TRASH_OWNER_INDEX: RefCell<TrashOwnerIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(TRASH_OWNER_INDEX_MEMORY_ID),
+        )
+    );
+
+    static TRASH_EXPIRY_INDEX: RefCell<TrashExpiryIndex> = RefCell::new(
+        StableBTreeMap::init(
+            get_memory(TRASH_EXPIRY_INDEX_MEMORY_ID),
         )
     );
+
+    static CONFIG: RefCell<ConfigCell> = RefCell::new(
+        StableCell::init(get_memory(CONFIG_MEMORY_ID), Config::default())
+            .expect("failed to initialize config cell")
+    );
+
+    static SCHEMA_VERSION: RefCell<SchemaVersionCell> = RefCell::new(
+        StableCell::init(get_memory(SCHEMA_VERSION_MEMORY_ID), 0)
+            .expect("failed to initialize schema version cell")
+    );
+}
+
+pub fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(id))
 }
 
 pub fn with_todo_store<R>(f: impl FnOnce(&TodoStorage) -> R) -> R {
@@ -35,16 +393,825 @@ pub fn with_todo_store_mut<R>(f: impl FnOnce(&mut TodoStorage) -> R) -> R {
     TODO_STORE.with(|store| f(&mut store.borrow_mut()))
 }
 
+fn created_index_key(
+    owner: Principal,
+    (created_at, id): RecencyPosition,
+) -> (Principal, (u64, TodoId)) {
+    (owner, (!created_at, !id))
+}
+
+/// Adds `todo` to every secondary index. Call after inserting it into the store.
+pub fn index_todo(todo: &Todo) {
+    CREATED_INDEX.with(|index| {
+        index.borrow_mut().insert(
+            created_index_key(todo.owner, (todo.created_at, todo.id)),
+            (),
+        );
+    });
+    OWNER_COUNTS.with(|counts| {
+        let mut counts = counts.borrow_mut();
+        let count = counts.get(&todo.owner).unwrap_or(0);
+        counts.insert(todo.owner, count + 1);
+    });
+    index_todo_bytes(todo);
+    index_todo_text(todo);
+    index_todo_due_at(todo);
+    index_todo_tags(todo);
+    index_todo_list(todo);
+    index_todo_parent(todo);
+    index_todo_rank(todo);
+    index_todo_recurrence(todo);
+    index_todo_reminder(todo);
+}
+
+/// Removes `todo` from every secondary index. Call with the stored version
+/// before it is removed or replaced.
+pub fn unindex_todo(todo: &Todo) {
+    CREATED_INDEX.with(|index| {
+        index
+            .borrow_mut()
+            .remove(&created_index_key(todo.owner, (todo.created_at, todo.id)));
+    });
+    OWNER_COUNTS.with(|counts| {
+        let mut counts = counts.borrow_mut();
+        match counts.get(&todo.owner).unwrap_or(0) {
+            0 | 1 => counts.remove(&todo.owner),
+            count => counts.insert(todo.owner, count - 1),
+        };
+    });
+    unindex_todo_bytes(todo);
+    unindex_todo_text(todo);
+    unindex_todo_due_at(todo);
+    unindex_todo_tags(todo);
+    unindex_todo_list(todo);
+    unindex_todo_parent(todo);
+    unindex_todo_rank(todo);
+    unindex_todo_recurrence(todo);
+    unindex_todo_reminder(todo);
+}
+
+/// Moves a todo's index entries from its `previous` stored version to
+/// `current`, touching only indexes over fields that changed. Call after
+/// `current` has replaced `previous` in the store.
+pub fn reindex_todo(previous: &Todo, current: &Todo) {
+    if previous.text != current.text || previous.tags != current.tags {
+        unindex_todo_bytes(previous);
+        index_todo_bytes(current);
+    }
+    if previous.text != current.text {
+        unindex_todo_text(previous);
+        index_todo_text(current);
+    }
+    if previous.due_at != current.due_at {
+        unindex_todo_due_at(previous);
+        index_todo_due_at(current);
+    }
+    if previous.tags != current.tags {
+        unindex_todo_tags(previous);
+        index_todo_tags(current);
+    }
+    if previous.list_id != current.list_id {
+        unindex_todo_list(previous);
+        index_todo_list(current);
+    }
+    if previous.parent_id != current.parent_id {
+        unindex_todo_parent(previous);
+        index_todo_parent(current);
+    }
+    if previous.list_id != current.list_id || previous.rank != current.rank {
+        unindex_todo_rank(previous);
+        index_todo_rank(current);
+    }
+    if previous.due_at != current.due_at || previous.recurrence != current.recurrence {
+        unindex_todo_recurrence(previous);
+        index_todo_recurrence(current);
+    }
+    if previous.remind_at != current.remind_at {
+        unindex_todo_reminder(previous);
+        index_todo_reminder(current);
+    }
+}
+
+/// Bytes a todo counts against `Config::max_bytes_per_owner`: its text and
+/// tags, which are all a caller controls the size of.
+pub fn content_bytes(text: &str, tags: &[String]) -> u64 {
+    (text.len() + tags.iter().map(String::len).sum::<usize>()) as u64
+}
+
+/// Adds the content bytes of `todo` to its owner's total.
+pub fn index_todo_bytes(todo: &Todo) {
+    let bytes = content_bytes(&todo.text, &todo.tags);
+    OWNER_BYTES.with(|totals| {
+        let mut totals = totals.borrow_mut();
+        let total = totals.get(&todo.owner).unwrap_or(0);
+        totals.insert(todo.owner, total + bytes);
+    });
+}
+
+fn unindex_todo_bytes(todo: &Todo) {
+    let bytes = content_bytes(&todo.text, &todo.tags);
+    OWNER_BYTES.with(|totals| {
+        let mut totals = totals.borrow_mut();
+        match totals.get(&todo.owner).unwrap_or(0).saturating_sub(bytes) {
+            0 => totals.remove(&todo.owner),
+            total => totals.insert(todo.owner, total),
+        };
+    });
+}
+
+/// Adds the tokens of `todo.text` to the search index. Inserting a token
+/// that is already indexed is a no-op.
+pub fn index_todo_text(todo: &Todo) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for token in tokenize(&todo.text) {
+            index.insert(
+                TokenKey {
+                    owner: todo.owner,
+                    token,
+                    id: todo.id,
+                },
+                (),
+            );
+        }
+    });
+}
+
+fn unindex_todo_text(todo: &Todo) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for token in tokenize(&todo.text) {
+            index.remove(&TokenKey {
+                owner: todo.owner,
+                token,
+                id: todo.id,
+            });
+        }
+    });
+}
+
+fn index_todo_due_at(todo: &Todo) {
+    if let Some(due_at) = todo.due_at {
+        DUE_INDEX.with(|index| {
+            index
+                .borrow_mut()
+                .insert((todo.owner, (due_at, todo.id)), ());
+        });
+    }
+}
+
+fn unindex_todo_due_at(todo: &Todo) {
+    if let Some(due_at) = todo.due_at {
+        DUE_INDEX.with(|index| {
+            index.borrow_mut().remove(&(todo.owner, (due_at, todo.id)));
+        });
+    }
+}
+
+fn index_todo_tags(todo: &Todo) {
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in &todo.tags {
+            index.insert(
+                TokenKey {
+                    owner: todo.owner,
+                    token: tag.clone(),
+                    id: todo.id,
+                },
+                (),
+            );
+        }
+    });
+}
+
+fn unindex_todo_tags(todo: &Todo) {
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in &todo.tags {
+            index.remove(&TokenKey {
+                owner: todo.owner,
+                token: tag.clone(),
+                id: todo.id,
+            });
+        }
+    });
+}
+
+fn index_todo_list(todo: &Todo) {
+    if let Some(list_id) = todo.list_id {
+        LIST_TODO_INDEX.with(|index| {
+            index.borrow_mut().insert((list_id, todo.id), ());
+        });
+    }
+}
+
+fn unindex_todo_list(todo: &Todo) {
+    if let Some(list_id) = todo.list_id {
+        LIST_TODO_INDEX.with(|index| {
+            index.borrow_mut().remove(&(list_id, todo.id));
+        });
+    }
+}
+
+fn index_todo_parent(todo: &Todo) {
+    if let Some(parent_id) = todo.parent_id {
+        CHILD_INDEX.with(|index| {
+            index.borrow_mut().insert((parent_id, todo.id), ());
+        });
+    }
+}
+
+fn unindex_todo_parent(todo: &Todo) {
+    if let Some(parent_id) = todo.parent_id {
+        CHILD_INDEX.with(|index| {
+            index.borrow_mut().remove(&(parent_id, todo.id));
+        });
+    }
+}
+
+fn rank_key(todo: &Todo) -> RankKey {
+    RankKey {
+        owner: todo.owner,
+        list_id: todo.list_id,
+        rank: todo.rank.clone(),
+        id: todo.id,
+    }
+}
+
+/// Adds `todo` to the rank index. Todos stored before ranks existed are left
+/// out until the migration that ranks them.
+pub fn index_todo_rank(todo: &Todo) {
+    if !todo.rank.is_empty() {
+        RANK_INDEX.with(|index| {
+            index.borrow_mut().insert(rank_key(todo), ());
+        });
+    }
+}
+
+fn unindex_todo_rank(todo: &Todo) {
+    if !todo.rank.is_empty() {
+        RANK_INDEX.with(|index| {
+            index.borrow_mut().remove(&rank_key(todo));
+        });
+    }
+}
+
+/// Key of `todo` in the recurrence index. Only recurring todos with a due
+/// date have one.
+fn recurrence_key(todo: &Todo) -> Option<(u64, TodoId)> {
+    let due_at = todo.due_at?;
+    let rule = todo.recurrence.as_ref()?;
+    Some((next_occurrence(due_at, rule), todo.id))
+}
+
+fn index_todo_recurrence(todo: &Todo) {
+    if let Some(key) = recurrence_key(todo) {
+        RECURRENCE_INDEX.with(|index| {
+            index.borrow_mut().insert(key, ());
+        });
+    }
+}
+
+fn unindex_todo_recurrence(todo: &Todo) {
+    if let Some(key) = recurrence_key(todo) {
+        RECURRENCE_INDEX.with(|index| {
+            index.borrow_mut().remove(&key);
+        });
+    }
+}
+
+/// Ids of recurring todos whose next occurrence is due at or before
+/// `due_before`, soonest first, at most `take` of them.
+pub fn get_recurring_ids_due_by(due_before: u64, take: u64) -> Vec<TodoId> {
+    RECURRENCE_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(..=(due_before, TodoId::MAX))
+            .take(take as usize)
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+fn index_todo_reminder(todo: &Todo) {
+    if let Some(remind_at) = todo.remind_at {
+        REMINDER_INDEX.with(|index| {
+            index.borrow_mut().insert((remind_at, todo.id), ());
+        });
+    }
+}
+
+fn unindex_todo_reminder(todo: &Todo) {
+    if let Some(remind_at) = todo.remind_at {
+        REMINDER_INDEX.with(|index| {
+            index.borrow_mut().remove(&(remind_at, todo.id));
+        });
+    }
+}
+
+/// Ids of todos with a reminder at or before `remind_before`, soonest first,
+/// at most `take` of them.
+pub fn get_due_reminder_ids(remind_before: u64, take: u64) -> Vec<TodoId> {
+    REMINDER_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(..=(remind_before, TodoId::MAX))
+            .take(take as usize)
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// The lowest rank in `owner`'s `list_id`, or `None` for an empty list.
+pub fn get_first_rank(owner: Principal, list_id: Option<ListId>) -> Option<String> {
+    RANK_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(RankKey::scope_start(owner, list_id)..)
+            .next()
+            .filter(|(key, _)| key.in_scope(owner, list_id))
+            .map(|(key, _)| key.rank)
+    })
+}
+
+/// Ids of `owner`'s todos in `list_id`, in rank order.
+pub fn get_todo_ids_by_rank(owner: Principal, list_id: Option<ListId>) -> Vec<TodoId> {
+    RANK_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(RankKey::scope_start(owner, list_id)..)
+            .take_while(|(key, _)| key.in_scope(owner, list_id))
+            .map(|(key, _)| key.id)
+            .collect()
+    })
+}
+
+/// Ids of the direct subtasks of `parent_id`, in id order.
+pub fn get_child_ids(parent_id: TodoId) -> Vec<TodoId> {
+    CHILD_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((parent_id, TodoId::MIN)..=(parent_id, TodoId::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// Ids of every subtask below `id`, level by level, so each parent comes
+/// before its children.
+pub fn get_descendant_ids(id: TodoId) -> Vec<TodoId> {
+    let mut descendants = get_child_ids(id);
+    let mut next = 0;
+    while next < descendants.len() {
+        let children = get_child_ids(descendants[next]);
+        descendants.extend(children);
+        next += 1;
+    }
+    descendants
+}
+
+/// Ids of the todos in `list_id`, in id order.
+pub fn get_todo_ids_by_list(list_id: ListId) -> Vec<TodoId> {
+    LIST_TODO_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((list_id, TodoId::MIN)..=(list_id, TodoId::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+pub fn get_list(id: ListId) -> Option<TodoList> {
+    LIST_STORE.with(|store| store.borrow().get(&id))
+}
+
+/// Inserts or replaces `list`. A list only changes owner when it is removed
+/// and inserted again with the new one.
+pub fn insert_list(list: TodoList) {
+    OWNER_LIST_INDEX.with(|index| {
+        index.borrow_mut().insert((list.owner, list.id), ());
+    });
+    LIST_STORE.with(|store| {
+        store.borrow_mut().insert(list.id, list);
+    });
+}
+
+pub fn remove_list(id: ListId) -> Option<TodoList> {
+    let list = LIST_STORE.with(|store| store.borrow_mut().remove(&id))?;
+    OWNER_LIST_INDEX.with(|index| {
+        index.borrow_mut().remove(&(list.owner, id));
+    });
+    Some(list)
+}
+
+/// `owner`'s lists in creation order.
+pub fn get_lists_by_owner(owner: Principal) -> Vec<TodoList> {
+    let ids: Vec<ListId> = OWNER_LIST_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((owner, ListId::MIN)..=(owner, ListId::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    });
+    LIST_STORE.with(|store| {
+        let store = store.borrow();
+        ids.iter().filter_map(|id| store.get(id)).collect()
+    })
+}
+
+/// The role `principal` was given on `list_id`, if it is shared with them.
+pub fn get_share(list_id: ListId, principal: Principal) -> Option<ListRole> {
+    LIST_ACL.with(|acl| acl.borrow().get(&(list_id, principal)))
+}
+
+/// Shares `list_id` with `principal`, replacing any role they had.
+pub fn insert_share(list_id: ListId, principal: Principal, role: ListRole) {
+    LIST_ACL.with(|acl| {
+        acl.borrow_mut().insert((list_id, principal), role);
+    });
+    SHARED_WITH_INDEX.with(|index| {
+        index.borrow_mut().insert((principal, list_id), ());
+    });
+}
+
+/// Stops sharing `list_id` with `principal`, returning the role they had.
+pub fn remove_share(list_id: ListId, principal: Principal) -> Option<ListRole> {
+    let role = LIST_ACL.with(|acl| acl.borrow_mut().remove(&(list_id, principal)))?;
+    SHARED_WITH_INDEX.with(|index| {
+        index.borrow_mut().remove(&(principal, list_id));
+    });
+    Some(role)
+}
+
+/// Everyone `list_id` is shared with and their roles, in principal order.
+pub fn get_shares_by_list(list_id: ListId) -> Vec<(Principal, ListRole)> {
+    // Principals sort by length first, so the empty slice starts the range.
+    let start = (list_id, Principal::from_slice(&[]));
+    LIST_ACL.with(|acl| {
+        acl.borrow()
+            .range(start..)
+            .take_while(|((id, _), _)| *id == list_id)
+            .map(|((_, principal), role)| (principal, role))
+            .collect()
+    })
+}
+
+/// Ids of the lists shared with `principal`, in id order.
+pub fn get_list_ids_shared_with(principal: Principal) -> Vec<ListId> {
+    SHARED_WITH_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((principal, ListId::MIN)..=(principal, ListId::MAX))
+            .map(|((_, list_id), _)| list_id)
+            .collect()
+    })
+}
+
+pub fn is_admin(principal: Principal) -> bool {
+    ADMINS.with(|admins| admins.borrow().contains_key(&principal))
+}
+
+/// Adds `principal` to the admin set; returns whether it was new.
+pub fn insert_admin(principal: Principal) -> bool {
+    ADMINS.with(|admins| admins.borrow_mut().insert(principal, ()).is_none())
+}
+
+/// Removes `principal` from the admin set; returns whether it was there.
+pub fn remove_admin(principal: Principal) -> bool {
+    ADMINS.with(|admins| admins.borrow_mut().remove(&principal).is_some())
+}
+
+pub fn get_admins() -> Vec<Principal> {
+    ADMINS.with(|admins| {
+        admins
+            .borrow()
+            .iter()
+            .map(|(principal, _)| principal)
+            .collect()
+    })
+}
+
+pub fn is_banned(principal: Principal) -> bool {
+    BANNED.with(|banned| banned.borrow().contains_key(&principal))
+}
+
+/// Bans `principal` from `banned_at` on; returns whether they were not banned
+/// already.
+pub fn insert_ban(principal: Principal, banned_at: u64) -> bool {
+    BANNED.with(|banned| {
+        let mut banned = banned.borrow_mut();
+        if banned.contains_key(&principal) {
+            return false;
+        }
+        banned.insert(principal, banned_at);
+        true
+    })
+}
+
+/// Lifts the ban on `principal`; returns whether they were banned.
+pub fn remove_ban(principal: Principal) -> bool {
+    BANNED.with(|banned| banned.borrow_mut().remove(&principal).is_some())
+}
+
+pub fn get_banned() -> Vec<Principal> {
+    BANNED.with(|banned| {
+        banned
+            .borrow()
+            .iter()
+            .map(|(principal, _)| principal)
+            .collect()
+    })
+}
+
+/// Entry counts of the stores behind `GlobalStats` and the stable memory
+/// size. Cycles are left at zero for the caller to fill in.
+pub fn get_global_stats() -> GlobalStats {
+    GlobalStats {
+        todos: TODO_STORE.with(|store| store.borrow().len()),
+        owners: OWNER_COUNTS.with(|counts| counts.borrow().len()),
+        lists: LIST_STORE.with(|store| store.borrow().len()),
+        shares: LIST_ACL.with(|acl| acl.borrow().len()),
+        trashed_todos: TRASH_STORE.with(|store| store.borrow().len()),
+        notifications: NOTIFICATION_STORE.with(|store| store.borrow().len()),
+        admins: ADMINS.with(|admins| admins.borrow().len()),
+        banned: BANNED.with(|banned| banned.borrow().len()),
+        stable_memory_bytes: ic_stable_structures::Memory::size(&DefaultMemoryImpl::default())
+            * WASM_PAGE_SIZE,
+        cycles: 0,
+    }
+}
+
+/// Ids of `owner`'s todos due within the inclusive bounds, soonest first.
+pub fn get_todo_ids_by_due_date(
+    owner: Principal,
+    due_after: Option<u64>,
+    due_before: Option<u64>,
+) -> Vec<TodoId> {
+    let start = (owner, (due_after.unwrap_or(u64::MIN), TodoId::MIN));
+    let end = (owner, (due_before.unwrap_or(u64::MAX), TodoId::MAX));
+
+    DUE_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|((_, (_, id)), _)| id)
+            .collect()
+    })
+}
+
+/// Ids of `owner`'s todos carrying exactly `tag`, in id order.
+pub fn get_todo_ids_by_tag(owner: Principal, tag: &str) -> Vec<TodoId> {
+    let start = TokenKey {
+        owner,
+        token: tag.to_string(),
+        id: TodoId::MIN,
+    };
+    let end = TokenKey {
+        owner,
+        token: tag.to_string(),
+        id: TodoId::MAX,
+    };
+
+    TAG_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|(key, _)| key.id)
+            .collect()
+    })
+}
+
+/// `owner`'s todos with at least one token starting with `prefix`, mapped
+/// to the number of their tokens that do.
+pub fn get_todo_ids_by_token_prefix(owner: Principal, prefix: &str) -> BTreeMap<TodoId, u32> {
+    let start = TokenKey {
+        owner,
+        token: prefix.to_string(),
+        id: TodoId::MIN,
+    };
+
+    SEARCH_INDEX.with(|index| {
+        let mut hits = BTreeMap::new();
+        for (key, _) in index.borrow().range(start..) {
+            if key.owner != owner || !key.token.starts_with(prefix) {
+                break;
+            }
+            *hits.entry(key.id).or_insert(0) += 1;
+        }
+        hits
+    })
+}
+
+/// Ids of `owner`'s todos, newest first. Starts strictly after `after` when
+/// given, skips `skip` entries and returns at most `take` ids.
+pub fn get_todo_ids_by_recency(
+    owner: Principal,
+    after: Option<RecencyPosition>,
+    skip: u64,
+    take: u64,
+) -> Vec<TodoId> {
+    let start = match after {
+        Some(position) => Bound::Excluded(created_index_key(owner, position)),
+        None => Bound::Included((owner, (u64::MIN, TodoId::MIN))),
+    };
+    let end = Bound::Included((owner, (u64::MAX, TodoId::MAX)));
+
+    CREATED_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((start, end))
+            .skip(skip as usize)
+            .take(take as usize)
+            .map(|((_, (_, inverted_id)), _)| !inverted_id)
+            .collect()
+    })
+}
+
+/// Visits `owner`'s todos newest first, starting strictly after `after`,
+/// until `visit` breaks or the owner has no more todos.
+pub fn scan_todos_by_recency(
+    owner: Principal,
+    after: Option<RecencyPosition>,
+    mut visit: impl FnMut(Todo) -> ControlFlow<()>,
+) {
+    let start = match after {
+        Some(position) => Bound::Excluded(created_index_key(owner, position)),
+        None => Bound::Included((owner, (u64::MIN, TodoId::MIN))),
+    };
+    let end = Bound::Included((owner, (u64::MAX, TodoId::MAX)));
+
+    CREATED_INDEX.with(|index| {
+        let index = index.borrow();
+        with_todo_store(|store| {
+            for ((_, (_, inverted_id)), _) in index.range((start, end)) {
+                if let Some(todo) = store.get(&!inverted_id) {
+                    if visit(todo).is_break() {
+                        break;
+                    }
+                }
+            }
+        });
+    });
+}
+
+/// Stores a todo that has already been removed from the store and unindexed.
+pub fn insert_trashed_todo(trashed: TrashedTodo) {
+    let owner = trashed.todo.owner;
+    let (id, deleted_at) = (trashed.todo.id, trashed.deleted_at);
+
+    TRASH_STORE.with(|store| {
+        store.borrow_mut().insert(id, trashed);
+    });
+    TRASH_OWNER_INDEX.with(|index| {
+        index.borrow_mut().insert((owner, (!deleted_at, !id)), ());
+    });
+    TRASH_EXPIRY_INDEX.with(|index| {
+        index.borrow_mut().insert((deleted_at, id), ());
+    });
+}
+
+pub fn get_trashed_todo(id: TodoId) -> Option<TrashedTodo> {
+    TRASH_STORE.with(|store| store.borrow().get(&id))
+}
+
+/// Removes a trashed todo and its index entries, returning it if present.
+pub fn remove_trashed_todo(id: TodoId) -> Option<TrashedTodo> {
+    let trashed = TRASH_STORE.with(|store| store.borrow_mut().remove(&id))?;
+    let (owner, deleted_at) = (trashed.todo.owner, trashed.deleted_at);
+
+    TRASH_OWNER_INDEX.with(|index| {
+        index.borrow_mut().remove(&(owner, (!deleted_at, !id)));
+    });
+    TRASH_EXPIRY_INDEX.with(|index| {
+        index.borrow_mut().remove(&(deleted_at, id));
+    });
+    Some(trashed)
+}
+
+/// Ids of `owner`'s trashed todos, most recently deleted first.
+pub fn get_trashed_ids_by_owner(owner: Principal, skip: u64, take: u64) -> Vec<TodoId> {
+    let range = (owner, (u64::MIN, TodoId::MIN))..=(owner, (u64::MAX, TodoId::MAX));
+    TRASH_OWNER_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(range)
+            .skip(skip as usize)
+            .take(take as usize)
+            .map(|((_, (_, inverted_id)), _)| !inverted_id)
+            .collect()
+    })
+}
+
+pub fn get_trash_count_by_owner(owner: Principal) -> u64 {
+    let range = (owner, (u64::MIN, TodoId::MIN))..=(owner, (u64::MAX, TodoId::MAX));
+    TRASH_OWNER_INDEX.with(|index| index.borrow().range(range).count() as u64)
+}
+
+/// Ids of todos deleted at or before `deleted_before`, oldest first, at
+/// most `take` of them.
+pub fn get_expired_trash_ids(deleted_before: u64, take: u64) -> Vec<TodoId> {
+    TRASH_EXPIRY_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(..=(deleted_before, TodoId::MAX))
+            .take(take as usize)
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+pub fn insert_notification(notification: Notification) {
+    NOTIFICATION_STORE.with(|store| {
+        store
+            .borrow_mut()
+            .insert((notification.owner, notification.id), notification);
+    });
+}
+
+/// Removes one of `owner`'s notifications, returning it if present.
+pub fn remove_notification(owner: Principal, id: NotificationId) -> Option<Notification> {
+    NOTIFICATION_STORE.with(|store| store.borrow_mut().remove(&(owner, id)))
+}
+
+/// `owner`'s inbox, oldest delivery first.
+pub fn get_notifications_by_owner(owner: Principal) -> Vec<Notification> {
+    let range = (owner, NotificationId::MIN)..=(owner, NotificationId::MAX);
+    NOTIFICATION_STORE.with(|store| {
+        store
+            .borrow()
+            .range(range)
+            .map(|(_, notification)| notification)
+            .collect()
+    })
+}
+
+pub fn get_todo_count_by_owner(owner: Principal) -> u64 {
+    OWNER_COUNTS.with(|counts| counts.borrow().get(&owner).unwrap_or(0))
+}
+
+pub fn get_bytes_by_owner(owner: Principal) -> u64 {
+    OWNER_BYTES.with(|totals| totals.borrow().get(&owner).unwrap_or(0))
+}
+
+/// Drops every owner's byte total, ahead of recounting them.
+pub fn clear_owner_bytes() {
+    OWNER_BYTES.with(|totals| {
+        let mut totals = totals.borrow_mut();
+        let owners: Vec<Principal> = totals.iter().map(|(owner, _)| owner).collect();
+        for owner in owners {
+            totals.remove(&owner);
+        }
+    });
+}
+
 pub fn get_next_todo_id() -> TodoId {
+    next_counter_value(TODO_COUNTER_KEY)
+}
+
+pub fn get_next_list_id() -> ListId {
+    next_counter_value(LIST_COUNTER_KEY)
+}
+
+pub fn get_next_notification_id() -> NotificationId {
+    next_counter_value(NOTIFICATION_COUNTER_KEY)
+}
+
+fn next_counter_value(key: u8) -> u64 {
     ID_STORE.with(|store| {
         let mut store = store.borrow_mut();
-        let current_id = store.get(&TODO_COUNTER_KEY).unwrap_or(0);
+        let current_id = store.get(&key).unwrap_or(0);
         let next_id = current_id + 1;
-        store.insert(TODO_COUNTER_KEY, next_id);
+        store.insert(key, next_id);
         next_id
     })
 }
 
+/// Schema version of the data currently in stable memory. Zero means the
+/// data predates versioning.
+pub fn get_schema_version() -> u32 {
+    SCHEMA_VERSION.with(|cell| *cell.borrow().get())
+}
+
+pub fn set_schema_version(version: u32) {
+    SCHEMA_VERSION.with(|cell| {
+        cell.borrow_mut()
+            .set(version)
+            .expect("failed to write schema version");
+    });
+}
+
+pub fn get_config() -> Config {
+    CONFIG.with(|cell| cell.borrow().get().clone())
+}
+
+pub fn set_config(config: Config) {
+    CONFIG.with(|cell| {
+        cell.borrow_mut()
+            .set(config)
+            .expect("failed to write config");
+    });
+}
+
 pub fn init_storage() {
     ID_STORE.with(|store| {
         let mut store = store.borrow_mut();
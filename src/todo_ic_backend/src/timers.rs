@@ -0,0 +1,28 @@
+use crate::service::{
+    deliver_due_reminders, materialise_upcoming_occurrences, purge_expired_trash,
+};
+use std::time::Duration;
+
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const RECURRENCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const REMINDER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Registers the canister's recurring jobs. Timers live on the heap and do
+/// not survive upgrades, so this runs from both `init` and `post_upgrade`.
+pub fn start_timers() {
+    ic_cdk_timers::set_timer_interval(TRASH_PURGE_INTERVAL, || {
+        let purged = purge_expired_trash();
+        if purged > 0 {
+            ic_cdk::println!("Purged {} expired todos from the trash", purged);
+        }
+    });
+    ic_cdk_timers::set_timer_interval(RECURRENCE_INTERVAL, || {
+        let created = materialise_upcoming_occurrences();
+        if created > 0 {
+            ic_cdk::println!("Created {} upcoming recurring todos", created);
+        }
+    });
+    ic_cdk_timers::set_timer_interval(REMINDER_INTERVAL, || {
+        deliver_due_reminders();
+    });
+}
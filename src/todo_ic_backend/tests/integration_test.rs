@@ -1,423 +1,2469 @@
-use std::collections::HashMap;
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Todo {
-    pub id: u64,
-    pub text: String,
-    pub completed: bool,
-    pub created_at: u64,
-    pub updated_at: u64,
+//! Integration tests that install the real `todo_ic_backend` wasm in PocketIC.
+//!
+//! Build the canister first (`make test` does this) and point `POCKET_IC_BIN`
+//! at a PocketIC server binary. `TODO_IC_BACKEND_WASM` overrides the wasm path,
+//! and `TODO_IC_BACKEND_BASELINE_WASM` that of the first release.
+
+use candid::{decode_one, encode_args, encode_one, CandidType, Deserialize, Principal};
+use pocket_ic::{PocketIc, WasmResult};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use todo_ic_types::*;
+
+fn backend_wasm() -> Vec<u8> {
+    read_wasm(
+        "TODO_IC_BACKEND_WASM",
+        "../../target/wasm32-unknown-unknown/release/todo_ic_backend.wasm",
+        "make build-wasm",
+    )
 }
 
-#[derive(Debug)]
-pub struct CreateTodoInput {
-    pub text: String,
+/// The first release, which stored todos as JSON without owners.
+fn baseline_wasm() -> Vec<u8> {
+    read_wasm(
+        "TODO_IC_BACKEND_BASELINE_WASM",
+        "../../target/baseline/wasm32-unknown-unknown/release/todo_ic_backend.wasm",
+        "make baseline-wasm",
+    )
 }
 
-#[derive(Debug)]
-pub struct UpdateTodoInput {
-    pub id: u64,
-    pub text: Option<String>,
-    pub completed: Option<bool>,
+fn read_wasm(env_var: &str, default_path: &str, build_with: &str) -> Vec<u8> {
+    let path = std::env::var(env_var)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(default_path));
+    std::fs::read(&path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read canister wasm at {}: {}. Build it with `{}`.",
+            path.display(),
+            e,
+            build_with
+        )
+    })
 }
 
-#[derive(Debug)]
-pub struct PaginationInput {
-    pub offset: u64,
-    pub limit: u64,
+fn alice() -> Principal {
+    Principal::from_slice(&[1; 29])
 }
 
-#[derive(Debug, PartialEq)]
-pub struct TodosPage {
-    pub todos: Vec<Todo>,
-    pub total_count: u64,
-    pub has_more: bool,
+fn bob() -> Principal {
+    Principal::from_slice(&[2; 29])
 }
 
-#[derive(Debug, PartialEq)]
-pub enum TodoResult {
-    Ok(Todo),
-    Err(String),
+fn carol() -> Principal {
+    Principal::from_slice(&[3; 29])
 }
 
-#[derive(Debug, PartialEq)]
-pub enum TodosResult {
-    Ok(TodosPage),
-    Err(String),
+/// PocketIC makes the anonymous principal the controller of the canisters
+/// it creates.
+fn controller() -> Principal {
+    Principal::anonymous()
 }
 
-#[derive(Debug, PartialEq)]
-pub enum DeleteResult {
-    Ok(bool),
-    Err(String),
+struct TestEnv {
+    pic: PocketIc,
+    canister_id: Principal,
 }
 
-pub struct TodoService {
-    todos: HashMap<u64, Todo>,
-    next_id: u64,
-}
+impl TestEnv {
+    fn new() -> Self {
+        Self::install(encode_args(()).unwrap())
+    }
 
-impl Default for TodoService {
-    fn default() -> Self {
-        Self::new()
+    fn with_admins(admins: Vec<Principal>) -> Self {
+        Self::install(
+            encode_one(Some(InitArgs {
+                admins,
+                ..Default::default()
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn install(init_arg: Vec<u8>) -> Self {
+        Self::install_wasm(backend_wasm(), init_arg)
+    }
+
+    fn install_wasm(wasm: Vec<u8>, init_arg: Vec<u8>) -> Self {
+        let pic = PocketIc::new();
+        let canister_id = pic.create_canister();
+        pic.add_cycles(canister_id, 2_000_000_000_000);
+        pic.install_canister(canister_id, wasm, init_arg, None);
+        Self { pic, canister_id }
+    }
+
+    fn upgrade(&self) {
+        self.upgrade_with(encode_args(()).unwrap());
     }
-}
 
-impl TodoService {
-    pub fn new() -> Self {
-        Self {
-            todos: HashMap::new(),
-            next_id: 1,
+    fn upgrade_with(&self, upgrade_arg: Vec<u8>) {
+        self.pic
+            .upgrade_canister(self.canister_id, backend_wasm(), upgrade_arg, None)
+            .expect("Failed to upgrade canister");
+    }
+
+    fn update<R: CandidType + for<'de> Deserialize<'de>>(
+        &self,
+        sender: Principal,
+        method: &str,
+        payload: Vec<u8>,
+    ) -> R {
+        let result = self
+            .pic
+            .update_call(self.canister_id, sender, method, payload)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e));
+        decode_reply(method, result)
+    }
+
+    fn query<R: CandidType + for<'de> Deserialize<'de>>(
+        &self,
+        sender: Principal,
+        method: &str,
+        payload: Vec<u8>,
+    ) -> R {
+        let result = self
+            .pic
+            .query_call(self.canister_id, sender, method, payload)
+            .unwrap_or_else(|e| panic!("{} failed: {:?}", method, e));
+        decode_reply(method, result)
+    }
+
+    /// The message of a call an endpoint guard rejected, or of the ingress
+    /// `canister_inspect_message` dropped.
+    fn rejection(&self, sender: Principal, method: &str, payload: Vec<u8>) -> String {
+        match self
+            .pic
+            .update_call(self.canister_id, sender, method, payload)
+        {
+            Ok(WasmResult::Reject(message)) => message,
+            Err(error) => error.description,
+            other => panic!("{} should have been rejected, got {:?}", method, other),
         }
     }
 
-    fn get_current_time() -> u64 {
-        use std::time::{SystemTime, UNIX_EPOCH};
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64
+    fn add_todo(&self, sender: Principal, text: &str) -> TodoResult {
+        let input = CreateTodoInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        self.update(sender, "add_todo", encode_one(input).unwrap())
+    }
+
+    fn get_todo(&self, sender: Principal, id: u64) -> TodoResult {
+        self.query(sender, "get_todo", encode_one(id).unwrap())
+    }
+
+    fn get_all_todos(&self, sender: Principal, offset: u64, limit: u64) -> TodosResult {
+        let pagination = PaginationInput {
+            offset,
+            limit,
+            cursor: None,
+            filter: None,
+            sort: None,
+        };
+        self.query(sender, "get_all_todos", encode_one(pagination).unwrap())
+    }
+
+    fn get_todos_after(&self, sender: Principal, cursor: &str, limit: u64) -> TodosResult {
+        let pagination = PaginationInput {
+            offset: 0,
+            limit,
+            cursor: Some(cursor.to_string()),
+            filter: None,
+            sort: None,
+        };
+        self.query(sender, "get_all_todos", encode_one(pagination).unwrap())
     }
 
-    fn validate_todo_text(text: &str) -> Result<(), String> {
-        if text.trim().is_empty() {
-            return Err("Todo text cannot be empty".to_string());
+    fn query_todos(&self, sender: Principal, pagination: PaginationInput) -> TodosPage {
+        match self.query(sender, "get_all_todos", encode_one(pagination).unwrap()) {
+            TodosResult::Ok(page) => page,
+            TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
         }
-        if text.len() > 1000 {
-            return Err("Todo text cannot exceed 1000 characters".to_string());
+    }
+
+    fn search_todos(&self, sender: Principal, query: &str) -> TodosResult {
+        let pagination = PaginationInput {
+            offset: 0,
+            limit: 10,
+            cursor: None,
+            filter: None,
+            sort: None,
+        };
+        self.query(
+            sender,
+            "search_todos",
+            encode_args((query.to_string(), pagination)).unwrap(),
+        )
+    }
+
+    fn search_texts(&self, sender: Principal, query: &str) -> Vec<String> {
+        match self.search_todos(sender, query) {
+            TodosResult::Ok(page) => page.todos.into_iter().map(|t| t.text).collect(),
+            TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
         }
-        Ok(())
     }
 
-    fn validate_pagination(pagination: &PaginationInput) -> Result<(), String> {
-        if pagination.limit == 0 {
-            return Err("Limit must be greater than 0".to_string());
+    fn update_todo_text(&self, sender: Principal, id: u64, text: &str) -> TodoResult {
+        self.update(
+            sender,
+            "update_todo_text",
+            encode_args((id, text.to_string())).unwrap(),
+        )
+    }
+
+    fn update_todo_completed(&self, sender: Principal, id: u64, completed: bool) -> TodoResult {
+        self.update(
+            sender,
+            "update_todo_completed",
+            encode_args((id, completed)).unwrap(),
+        )
+    }
+
+    fn delete_todo(&self, sender: Principal, id: u64) -> DeleteResult {
+        self.update(sender, "delete_todo", encode_one(id).unwrap())
+    }
+
+    fn update_todo(&self, sender: Principal, input: UpdateTodoInput) -> TodoResult {
+        self.update(sender, "update_todo", encode_one(input).unwrap())
+    }
+
+    fn add_todos(&self, sender: Principal, texts: &[&str]) -> TodoBatchResult {
+        let inputs: Vec<CreateTodoInput> = texts
+            .iter()
+            .map(|text| CreateTodoInput {
+                text: text.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        self.update(sender, "add_todos", encode_one(inputs).unwrap())
+    }
+
+    fn update_todos(&self, sender: Principal, inputs: Vec<UpdateTodoInput>) -> TodoBatchResult {
+        self.update(sender, "update_todos", encode_one(inputs).unwrap())
+    }
+
+    fn delete_todos(&self, sender: Principal, ids: Vec<u64>) -> DeleteBatchResult {
+        self.update(sender, "delete_todos", encode_one(ids).unwrap())
+    }
+
+    fn list_trash(&self, sender: Principal) -> TrashPage {
+        let pagination = PaginationInput {
+            offset: 0,
+            limit: 10,
+            cursor: None,
+            filter: None,
+            sort: None,
+        };
+        match self.query(sender, "list_trash", encode_one(pagination).unwrap()) {
+            TrashResult::Ok(page) => page,
+            TrashResult::Err(e) => panic!("Expected success, got error: {:?}", e),
         }
-        if pagination.limit > 100 {
-            return Err("Limit cannot exceed 100".to_string());
+    }
+
+    fn restore_todo(&self, sender: Principal, id: u64) -> TodoResult {
+        self.update(sender, "restore_todo", encode_one(id).unwrap())
+    }
+
+    fn empty_trash(&self, sender: Principal) -> u64 {
+        self.update(sender, "empty_trash", encode_args(()).unwrap())
+    }
+
+    fn add_subtask(&self, sender: Principal, parent_id: u64, text: &str) -> TodoResult {
+        let input = CreateTodoInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        self.update(
+            sender,
+            "add_subtask",
+            encode_args((parent_id, input)).unwrap(),
+        )
+    }
+
+    fn create_subtask(&self, sender: Principal, parent_id: u64, text: &str) -> Todo {
+        match self.add_subtask(sender, parent_id, text) {
+            TodoResult::Ok(todo) => todo,
+            TodoResult::Err(e) => panic!("Failed to create subtask: {:?}", e),
         }
-        Ok(())
     }
 
-    pub fn add_todo(&mut self, input: CreateTodoInput) -> TodoResult {
-        if let Err(err) = Self::validate_todo_text(&input.text) {
-            return TodoResult::Err(err);
+    fn get_todo_with_subtasks(&self, sender: Principal, id: u64) -> TodoWithSubtasks {
+        match self.query(sender, "get_todo_with_subtasks", encode_one(id).unwrap()) {
+            SubtasksResult::Ok(tree) => *tree,
+            SubtasksResult::Err(e) => panic!("Expected success, got error: {:?}", e),
         }
+    }
 
-        let now = Self::get_current_time();
-        let id = self.next_id;
-        self.next_id += 1;
+    fn move_subtree(&self, sender: Principal, id: u64, parent_id: Option<u64>) -> TodoResult {
+        self.update(
+            sender,
+            "move_subtree",
+            encode_args((id, parent_id)).unwrap(),
+        )
+    }
 
-        let todo = Todo {
-            id,
-            text: input.text.trim().to_string(),
-            completed: false,
-            created_at: now,
-            updated_at: now,
-        };
+    fn move_todo(
+        &self,
+        sender: Principal,
+        id: u64,
+        before: Option<u64>,
+        after: Option<u64>,
+    ) -> TodoResult {
+        self.update(
+            sender,
+            "move_todo",
+            encode_args((id, before, after)).unwrap(),
+        )
+    }
 
-        self.todos.insert(id, todo.clone());
-        TodoResult::Ok(todo)
+    fn ids_in_user_order(&self, sender: Principal, list_id: Option<u64>) -> Vec<u64> {
+        let page = self.query_todos(
+            sender,
+            PaginationInput {
+                offset: 0,
+                limit: 10,
+                cursor: None,
+                filter: list_id.map(|list_id| TodoFilter {
+                    list_id: Some(list_id),
+                    ..Default::default()
+                }),
+                sort: Some(TodoSort {
+                    field: SortField::Rank,
+                    direction: SortDirection::Asc,
+                }),
+            },
+        );
+        page.todos.iter().map(|t| t.id).collect()
     }
 
-    pub fn get_todo(&self, id: u64) -> TodoResult {
-        match self.todos.get(&id) {
-            Some(todo) => TodoResult::Ok(todo.clone()),
-            None => TodoResult::Err("Todo not found".to_string()),
+    fn create_list(&self, sender: Principal, name: &str) -> TodoList {
+        let input = CreateListInput {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        match self.update(sender, "create_list", encode_one(input).unwrap()) {
+            ListResult::Ok(list) => list,
+            ListResult::Err(e) => panic!("Failed to create list: {:?}", e),
         }
     }
 
-    pub fn get_all_todos(&self, pagination: PaginationInput) -> TodosResult {
-        if let Err(err) = Self::validate_pagination(&pagination) {
-            return TodosResult::Err(err);
+    fn get_lists(&self, sender: Principal, include_archived: bool) -> Vec<TodoList> {
+        self.query(sender, "get_lists", encode_one(include_archived).unwrap())
+    }
+
+    fn update_list(&self, sender: Principal, input: UpdateListInput) -> ListResult {
+        self.update(sender, "update_list", encode_one(input).unwrap())
+    }
+
+    fn delete_list(&self, sender: Principal, id: u64) -> DeleteResult {
+        self.update(sender, "delete_list", encode_one(id).unwrap())
+    }
+
+    fn share_list(
+        &self,
+        sender: Principal,
+        list_id: u64,
+        to: Principal,
+        role: ListRole,
+    ) -> ShareResult {
+        self.update(
+            sender,
+            "share_list",
+            encode_args((list_id, to, role)).unwrap(),
+        )
+    }
+
+    fn revoke_share(&self, sender: Principal, list_id: u64, from: Principal) -> DeleteResult {
+        self.update(
+            sender,
+            "revoke_share",
+            encode_args((list_id, from)).unwrap(),
+        )
+    }
+
+    fn get_list_shares(&self, sender: Principal, list_id: u64) -> SharesResult {
+        self.query(sender, "get_list_shares", encode_one(list_id).unwrap())
+    }
+
+    fn list_shared_with_me(&self, sender: Principal) -> Vec<SharedList> {
+        self.query(sender, "list_shared_with_me", encode_args(()).unwrap())
+    }
+
+    fn list_todo_ids(&self, sender: Principal, list_id: u64) -> Vec<u64> {
+        let page = self.query_todos(
+            sender,
+            PaginationInput {
+                offset: 0,
+                limit: 10,
+                cursor: None,
+                filter: Some(TodoFilter {
+                    list_id: Some(list_id),
+                    ..Default::default()
+                }),
+                sort: None,
+            },
+        );
+        page.todos.iter().map(|t| t.id).collect()
+    }
+
+    fn get_notifications(&self, sender: Principal) -> Vec<Notification> {
+        self.query(sender, "get_notifications", encode_args(()).unwrap())
+    }
+
+    fn ack_notifications(&self, sender: Principal, ids: Vec<u64>) -> u64 {
+        self.update(sender, "ack_notifications", encode_one(ids).unwrap())
+    }
+
+    fn get_todo_count(&self, sender: Principal) -> u64 {
+        self.query(sender, "get_todo_count", encode_args(()).unwrap())
+    }
+
+    fn now(&self) -> u64 {
+        self.pic
+            .get_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    }
+
+    fn create_todo_with(&self, sender: Principal, input: CreateTodoInput) -> Todo {
+        match self.update(sender, "add_todo", encode_one(input).unwrap()) {
+            TodoResult::Ok(todo) => todo,
+            TodoResult::Err(e) => panic!("Failed to create todo: {:?}", e),
         }
+    }
 
-        let mut all_todos: Vec<Todo> = self.todos.values().cloned().collect();
-        all_todos.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    fn create_sample_todo(&self, sender: Principal, text: &str) -> Todo {
+        match self.add_todo(sender, text) {
+            TodoResult::Ok(todo) => todo,
+            TodoResult::Err(e) => panic!("Failed to create todo: {:?}", e),
+        }
+    }
+}
 
-        let total_count = all_todos.len() as u64;
-        let start_index = pagination.offset as usize;
+fn decode_reply<R: CandidType + for<'de> Deserialize<'de>>(method: &str, result: WasmResult) -> R {
+    match result {
+        WasmResult::Reply(bytes) => decode_one(&bytes)
+            .unwrap_or_else(|e| panic!("Failed to decode {} reply: {}", method, e)),
+        WasmResult::Reject(message) => panic!("{} was rejected: {}", method, message),
+    }
+}
 
-        if start_index >= all_todos.len() {
-            return TodosResult::Ok(TodosPage {
-                todos: vec![],
-                total_count,
-                has_more: false,
-            });
+#[test]
+fn test_add_todo_success() {
+    let env = TestEnv::new();
+
+    match env.add_todo(alice(), "Learn Rust") {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.id, 1);
+            assert_eq!(todo.owner, alice());
+            assert_eq!(todo.text, "Learn Rust");
+            assert!(!todo.completed);
+            assert!(todo.created_at > 0);
+            assert_eq!(todo.created_at, todo.updated_at);
         }
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+}
 
-        let end_index = std::cmp::min(start_index + pagination.limit as usize, all_todos.len());
+#[test]
+fn test_add_todo_empty_text() {
+    let env = TestEnv::new();
 
-        let paginated_todos = all_todos[start_index..end_index].to_vec();
-        let has_more = end_index < all_todos.len();
+    match env.add_todo(alice(), "   ") {
+        TodoResult::Ok(_) => panic!("Expected error for empty text"),
+        TodoResult::Err(e) => assert_eq!(e, TodoError::validation("text", "cannot be empty")),
+    }
+}
 
-        TodosResult::Ok(TodosPage {
-            todos: paginated_todos,
-            total_count,
-            has_more,
-        })
+#[test]
+fn test_add_todo_max_length_text_is_stored() {
+    let env = TestEnv::new();
+    let text = "\"".repeat(1000);
+
+    let todo = env.create_sample_todo(alice(), &text);
+
+    match env.get_todo(alice(), todo.id) {
+        TodoResult::Ok(stored) => assert_eq!(stored.text, text),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+}
 
-    pub fn update_todo(&mut self, input: UpdateTodoInput) -> TodoResult {
-        match self.todos.get_mut(&input.id) {
-            Some(todo) => {
-                let mut updated = false;
+#[test]
+fn test_get_todo_not_found() {
+    let env = TestEnv::new();
 
-                if let Some(text) = input.text {
-                    if let Err(err) = Self::validate_todo_text(&text) {
-                        return TodoResult::Err(err);
-                    }
-                    todo.text = text.trim().to_string();
-                    updated = true;
-                }
+    match env.get_todo(alice(), 999) {
+        TodoResult::Ok(_) => panic!("Expected error for non-existent todo"),
+        TodoResult::Err(e) => assert_eq!(e, TodoError::NotFound { id: 999 }),
+    }
+}
 
-                if let Some(completed) = input.completed {
-                    todo.completed = completed;
-                    updated = true;
-                }
+#[test]
+fn test_get_all_todos_empty() {
+    let env = TestEnv::new();
 
-                if updated {
-                    todo.updated_at = Self::get_current_time();
-                }
+    match env.get_all_todos(alice(), 0, 10) {
+        TodosResult::Ok(page) => {
+            assert!(page.todos.is_empty());
+            assert_eq!(page.total_count, 0);
+            assert!(!page.has_more);
+        }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+}
 
-                TodoResult::Ok(todo.clone())
-            }
-            None => TodoResult::Err("Todo not found".to_string()),
+#[test]
+fn test_get_all_todos_newest_first_with_pagination() {
+    let env = TestEnv::new();
+    for text in ["First", "Second", "Third"] {
+        env.create_sample_todo(alice(), text);
+        env.pic.advance_time(Duration::from_secs(1));
+    }
+
+    match env.get_all_todos(alice(), 0, 2) {
+        TodosResult::Ok(page) => {
+            let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+            assert_eq!(texts, vec!["Third", "Second"]);
+            assert_eq!(page.total_count, 3);
+            assert!(page.has_more);
         }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
 
-    pub fn delete_todo(&mut self, id: u64) -> DeleteResult {
-        match self.todos.remove(&id) {
-            Some(_) => DeleteResult::Ok(true),
-            None => DeleteResult::Err("Todo not found".to_string()),
+    match env.get_all_todos(alice(), 2, 2) {
+        TodosResult::Ok(page) => {
+            assert_eq!(page.todos.len(), 1);
+            assert_eq!(page.todos[0].text, "First");
+            assert!(!page.has_more);
         }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+}
 
-    pub fn get_todo_count(&self) -> u64 {
-        self.todos.len() as u64
+#[test]
+fn test_cursor_pagination_is_stable_under_inserts() {
+    let env = TestEnv::new();
+    for text in ["First", "Second", "Third"] {
+        env.create_sample_todo(alice(), text);
+        env.pic.advance_time(Duration::from_secs(1));
     }
+
+    let cursor = match env.get_all_todos(alice(), 0, 2) {
+        TodosResult::Ok(page) => {
+            assert!(page.has_more);
+            page.next_cursor.expect("first page should return a cursor")
+        }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    };
+
+    // A todo added between page loads must not shift the next page.
+    env.create_sample_todo(alice(), "Fourth");
+
+    match env.get_todos_after(alice(), &cursor, 2) {
+        TodosResult::Ok(page) => {
+            let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+            assert_eq!(texts, vec!["First"]);
+            assert_eq!(page.total_count, 4);
+            assert!(!page.has_more);
+            assert_eq!(page.next_cursor, None);
+        }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+}
+
+#[test]
+fn test_invalid_cursor_is_rejected() {
+    let env = TestEnv::new();
+
+    match env.get_todos_after(alice(), "not-a-cursor", 10) {
+        TodosResult::Ok(_) => panic!("Expected error for invalid cursor"),
+        TodosResult::Err(e) => assert_eq!(
+            e,
+            TodoError::validation("cursor", "is not a valid page cursor")
+        ),
+    }
+}
+
+#[test]
+fn test_filter_open_items_oldest_first() {
+    let env = TestEnv::new();
+    let mut ids = Vec::new();
+    for text in ["Write report", "Send invoice", "Review report"] {
+        ids.push(env.create_sample_todo(alice(), text).id);
+        env.pic.advance_time(Duration::from_secs(1));
+    }
+    env.update_todo_completed(alice(), ids[1], true);
+
+    let page = env.query_todos(
+        alice(),
+        PaginationInput {
+            offset: 0,
+            limit: 10,
+            cursor: None,
+            filter: Some(TodoFilter {
+                completed: Some(false),
+                ..Default::default()
+            }),
+            sort: Some(TodoSort {
+                field: SortField::CreatedAt,
+                direction: SortDirection::Asc,
+            }),
+        },
+    );
+    let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(texts, vec!["Write report", "Review report"]);
+    assert_eq!(page.total_count, 2);
+    assert_eq!(page.next_cursor, None);
+
+    let page = env.query_todos(
+        alice(),
+        PaginationInput {
+            offset: 0,
+            limit: 10,
+            cursor: None,
+            filter: Some(TodoFilter {
+                text_contains: Some("REPORT".to_string()),
+                ..Default::default()
+            }),
+            sort: Some(TodoSort {
+                field: SortField::Text,
+                direction: SortDirection::Asc,
+            }),
+        },
+    );
+    let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(texts, vec!["Review report", "Write report"]);
 }
 
-fn create_test_service() -> TodoService {
-    TodoService::new()
+#[test]
+fn test_priority_due_date_and_tags_are_stored_normalised() {
+    let env = TestEnv::new();
+    let todo = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "File taxes".to_string(),
+            priority: Some(Priority::High),
+            due_at: Some(42),
+            tags: Some(vec![
+                "Home".to_string(),
+                " finance ".to_string(),
+                "home".to_string(),
+            ]),
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(todo.priority, Some(Priority::High));
+    assert_eq!(todo.due_at, Some(42));
+    assert_eq!(todo.tags, vec!["finance", "home"]);
+
+    let result = env.add_todo(alice(), "Plain");
+    assert!(
+        matches!(result, TodoResult::Ok(todo) if todo.tags.is_empty() && todo.priority.is_none())
+    );
 }
 
-fn create_sample_todo(service: &mut TodoService, text: &str) -> Todo {
+#[test]
+fn test_invalid_tags_are_rejected() {
+    let env = TestEnv::new();
     let input = CreateTodoInput {
+        text: "Tagged".to_string(),
+        tags: Some(vec!["two words".to_string()]),
+        ..Default::default()
+    };
+
+    let result: TodoResult = env.update(alice(), "add_todo", encode_one(input).unwrap());
+    assert_eq!(
+        result,
+        TodoResult::Err(TodoError::validation(
+            "tags",
+            "may only contain letters, digits, '-' and '_'"
+        ))
+    );
+}
+
+#[test]
+fn test_overdue_due_this_week_and_tagged_queries() {
+    let env = TestEnv::new();
+    let day = 24 * 60 * 60 * 1_000_000_000;
+    let now = env.now();
+    let due = |text: &str, due_at: u64, tag: &str| CreateTodoInput {
         text: text.to_string(),
+        due_at: Some(due_at),
+        tags: Some(vec![tag.to_string()]),
+        ..Default::default()
     };
-    match service.add_todo(input) {
-        TodoResult::Ok(todo) => todo,
-        TodoResult::Err(e) => panic!("Failed to create todo: {}", e),
+    let late = env.create_todo_with(alice(), due("Late report", now - day, "work"));
+    let soon = env.create_todo_with(alice(), due("Dentist", now + 2 * day, "health"));
+    env.create_todo_with(alice(), due("Conference", now + 30 * day, "work"));
+    let done = env.create_todo_with(alice(), due("Old chore", now - 2 * day, "home"));
+    env.update_todo_completed(alice(), done.id, true);
+    env.create_todo_with(bob(), due("Bob's late item", now - day, "work"));
+
+    let query = |filter: TodoFilter| {
+        env.query_todos(
+            alice(),
+            PaginationInput {
+                offset: 0,
+                limit: 10,
+                cursor: None,
+                filter: Some(filter),
+                sort: None,
+            },
+        )
+    };
+    let ids = |page: TodosPage| page.todos.iter().map(|t| t.id).collect::<Vec<_>>();
+
+    let overdue = query(TodoFilter {
+        completed: Some(false),
+        due_before: Some(now),
+        ..Default::default()
+    });
+    assert_eq!(ids(overdue), vec![late.id]);
+
+    let this_week = query(TodoFilter {
+        due_after: Some(now),
+        due_before: Some(now + 7 * day),
+        ..Default::default()
+    });
+    assert_eq!(ids(this_week), vec![soon.id]);
+
+    let tagged = query(TodoFilter {
+        tag: Some("WORK".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(tagged.total_count, 2);
+
+    // Clearing the due date drops the todo from the due index.
+    env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: late.id,
+            due_at: Some(None),
+            ..Default::default()
+        },
+    );
+    let overdue = query(TodoFilter {
+        due_before: Some(now),
+        completed: Some(false),
+        ..Default::default()
+    });
+    assert!(overdue.todos.is_empty());
+}
+
+#[test]
+fn test_invalid_query_is_rejected() {
+    let env = TestEnv::new();
+
+    let result: TodosResult = env.query(
+        alice(),
+        "get_all_todos",
+        encode_one(PaginationInput {
+            offset: 0,
+            limit: 10,
+            cursor: None,
+            filter: Some(TodoFilter {
+                created_after: Some(10),
+                created_before: Some(5),
+                ..Default::default()
+            }),
+            sort: None,
+        })
+        .unwrap(),
+    );
+
+    assert_eq!(
+        result,
+        TodosResult::Err(TodoError::validation(
+            "created_after",
+            "cannot be later than created_before"
+        ))
+    );
+}
+
+#[test]
+fn test_search_matches_prefixes_of_every_term() {
+    let env = TestEnv::new();
+    for text in [
+        "Write quarterly report",
+        "Report bug in reporting tool",
+        "Buy milk",
+        "Review report draft",
+    ] {
+        env.create_sample_todo(alice(), text);
+        env.pic.advance_time(Duration::from_secs(1));
     }
+    env.create_sample_todo(bob(), "Bob's report");
+
+    // Two matching words outrank one; ties go to the newest todo.
+    assert_eq!(
+        env.search_texts(alice(), "rep"),
+        vec![
+            "Report bug in reporting tool",
+            "Review report draft",
+            "Write quarterly report",
+        ]
+    );
+    assert_eq!(
+        env.search_texts(alice(), "REPORT dr"),
+        vec!["Review report draft"]
+    );
+    assert!(env.search_texts(alice(), "report milk").is_empty());
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[test]
+fn test_search_index_follows_updates_and_deletes() {
+    let env = TestEnv::new();
+    let todo = env.create_sample_todo(alice(), "Call plumber");
 
-    #[test]
-    fn test_add_todo_success() {
-        let mut service = create_test_service();
-        let input = CreateTodoInput {
-            text: "Learn Rust".to_string(),
-        };
+    env.update_todo_text(alice(), todo.id, "Call electrician");
+    assert!(env.search_texts(alice(), "plumber").is_empty());
+    assert_eq!(env.search_texts(alice(), "elec"), vec!["Call electrician"]);
 
-        let result = service.add_todo(input);
+    env.delete_todo(alice(), todo.id);
+    assert!(env.search_texts(alice(), "call").is_empty());
+}
 
-        match result {
-            TodoResult::Ok(todo) => {
-                assert_eq!(todo.id, 1);
-                assert_eq!(todo.text, "Learn Rust");
-                assert!(!todo.completed);
-                assert!(todo.created_at > 0);
-                assert_eq!(todo.created_at, todo.updated_at);
-            }
-            TodoResult::Err(e) => panic!("Expected success, got error: {}", e),
+#[test]
+fn test_search_rejects_queries_without_words() {
+    let env = TestEnv::new();
+
+    assert_eq!(
+        env.search_todos(alice(), " -- "),
+        TodosResult::Err(TodoError::validation(
+            "query",
+            "must contain at least one word"
+        ))
+    );
+}
+
+#[test]
+fn test_pagination_limits() {
+    let env = TestEnv::new();
+
+    match env.get_all_todos(alice(), 0, 0) {
+        TodosResult::Ok(_) => panic!("Expected error for limit 0"),
+        TodosResult::Err(e) => {
+            assert_eq!(e, TodoError::validation("limit", "must be greater than 0"))
         }
     }
 
-    #[test]
-    fn test_add_todo_empty_text() {
-        let mut service = create_test_service();
-        let input = CreateTodoInput {
-            text: "".to_string(),
-        };
+    match env.get_all_todos(alice(), 0, 101) {
+        TodosResult::Ok(_) => panic!("Expected error for limit 101"),
+        TodosResult::Err(e) => assert_eq!(e, TodoError::validation("limit", "cannot exceed 100")),
+    }
+}
 
-        let result = service.add_todo(input);
+#[test]
+fn test_update_todo_text() {
+    let env = TestEnv::new();
+    let created = env.create_sample_todo(alice(), "Original text");
 
-        match result {
-            TodoResult::Ok(_) => panic!("Expected error for empty text"),
-            TodoResult::Err(e) => assert_eq!(e, "Todo text cannot be empty"),
+    match env.update_todo_text(alice(), created.id, "Updated text") {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.text, "Updated text");
+            assert!(todo.updated_at >= created.updated_at);
         }
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+}
+
+#[test]
+fn test_update_todo_changes_both_fields_at_once() {
+    let env = TestEnv::new();
+    let created = env.create_sample_todo(alice(), "Draft");
 
-    #[test]
-    fn test_get_todo_success() {
-        let mut service = create_test_service();
-        let created_todo = create_sample_todo(&mut service, "Test todo");
+    let updated = match env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: created.id,
+            text: Some("Final".to_string()),
+            completed: Some(true),
+            expected_version: Some(created.version),
+            ..Default::default()
+        },
+    ) {
+        TodoResult::Ok(todo) => todo,
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    };
+    assert_eq!(updated.text, "Final");
+    assert!(updated.completed);
+}
 
-        let result = service.get_todo(created_todo.id);
+#[test]
+fn test_stale_update_is_rejected_with_conflict() {
+    let env = TestEnv::new();
+    let created = env.create_sample_todo(alice(), "Shared");
 
-        match result {
-            TodoResult::Ok(todo) => {
-                assert_eq!(todo.id, created_todo.id);
-                assert_eq!(todo.text, created_todo.text);
-            }
-            TodoResult::Err(e) => panic!("Expected success, got error: {}", e),
-        }
+    // Another tab edits the todo first, possibly at the same time.
+    let current = match env.update_todo_text(alice(), created.id, "Edited elsewhere") {
+        TodoResult::Ok(todo) => todo,
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    };
+
+    let stale = env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: created.id,
+            text: Some("Overwrite".to_string()),
+            expected_version: Some(created.version),
+            ..Default::default()
+        },
+    );
+    assert_eq!(current.version, created.version + 1);
+    assert_eq!(
+        stale,
+        TodoResult::Err(TodoError::Conflict {
+            id: created.id,
+            version: current.version,
+        })
+    );
+    assert_eq!(env.get_todo(alice(), created.id), TodoResult::Ok(current));
+}
+
+#[test]
+fn test_delete_todo_success() {
+    let env = TestEnv::new();
+    let created = env.create_sample_todo(alice(), "To be deleted");
+
+    assert_eq!(env.delete_todo(alice(), created.id), DeleteResult::Ok(true));
+
+    match env.get_todo(alice(), created.id) {
+        TodoResult::Err(e) => assert_eq!(e, TodoError::NotFound { id: created.id }),
+        TodoResult::Ok(_) => panic!("Todo should be deleted"),
     }
+}
 
-    #[test]
-    fn test_get_todo_not_found() {
-        let service = create_test_service();
+#[test]
+fn test_delete_todo_not_found() {
+    let env = TestEnv::new();
 
-        let result = service.get_todo(999);
+    assert_eq!(
+        env.delete_todo(alice(), 999),
+        DeleteResult::Err(TodoError::NotFound { id: 999 })
+    );
+}
 
-        match result {
-            TodoResult::Ok(_) => panic!("Expected error for non-existent todo"),
-            TodoResult::Err(e) => assert_eq!(e, "Todo not found"),
+#[test]
+fn test_deleted_todo_can_be_restored_from_trash() {
+    let env = TestEnv::new();
+    let created = env.create_sample_todo(alice(), "Oops");
+
+    assert_eq!(env.delete_todo(alice(), created.id), DeleteResult::Ok(true));
+    assert_eq!(env.get_todo_count(alice()), 0);
+
+    let trash = env.list_trash(alice());
+    assert_eq!(trash.total_count, 1);
+    assert_eq!(trash.items[0].todo.id, created.id);
+    assert!(env.list_trash(bob()).items.is_empty());
+    assert_eq!(
+        env.restore_todo(bob(), created.id),
+        TodoResult::Err(TodoError::Unauthorized)
+    );
+
+    match env.restore_todo(alice(), created.id) {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.text, "Oops");
+            assert_eq!(todo.created_at, created.created_at);
         }
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+    assert_eq!(env.get_todo_count(alice()), 1);
+    assert_eq!(env.list_trash(alice()).total_count, 0);
+    assert_eq!(
+        env.restore_todo(alice(), created.id),
+        TodoResult::Err(TodoError::NotFound { id: created.id })
+    );
+}
 
-    #[test]
-    fn test_get_all_todos_empty() {
-        let service = create_test_service();
-        let pagination = PaginationInput {
-            offset: 0,
-            limit: 10,
+#[test]
+fn test_empty_trash_removes_todos_permanently() {
+    let env = TestEnv::new();
+    for text in ["One", "Two"] {
+        let todo = env.create_sample_todo(alice(), text);
+        env.delete_todo(alice(), todo.id);
+    }
+    let bobs = env.create_sample_todo(bob(), "Bob's");
+    env.delete_todo(bob(), bobs.id);
+
+    assert_eq!(env.empty_trash(alice()), 2);
+    // An empty trash is reported as nothing left to remove.
+    assert_eq!(env.empty_trash(alice()), 0);
+    assert_eq!(env.list_trash(alice()).total_count, 0);
+    assert_eq!(env.list_trash(bob()).total_count, 1);
+}
+
+#[test]
+fn test_trash_is_purged_after_retention() {
+    let env = TestEnv::new();
+    let old = env.create_sample_todo(alice(), "Old");
+    env.delete_todo(alice(), old.id);
+    env.pic.advance_time(Duration::from_secs(29 * 24 * 60 * 60));
+    let recent = env.create_sample_todo(alice(), "Recent");
+    env.delete_todo(alice(), recent.id);
+
+    // Past the 30-day retention of the first todo only; let the hourly timer run.
+    env.pic.advance_time(Duration::from_secs(2 * 24 * 60 * 60));
+    env.pic.tick();
+    env.pic.tick();
+
+    let trash = env.list_trash(alice());
+    let ids: Vec<u64> = trash.items.iter().map(|item| item.todo.id).collect();
+    assert_eq!(ids, vec![recent.id]);
+}
+
+#[test]
+fn test_controllers_change_the_trash_retention() {
+    let env = TestEnv::new();
+    let config = Config {
+        trash_retention_nanos: 60 * 60 * 1_000_000_000,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(controller(), "set_config", encode_one(config).unwrap());
+    assert!(matches!(set, ConfigResult::Ok(_)));
+    let todo = env.create_sample_todo(alice(), "Short-lived");
+    env.delete_todo(alice(), todo.id);
+
+    // Past the one-hour retention; let the hourly timer run.
+    env.pic.advance_time(Duration::from_secs(2 * 60 * 60));
+    env.pic.tick();
+    env.pic.tick();
+
+    assert!(env.list_trash(alice()).items.is_empty());
+}
+
+#[test]
+fn test_list_crud() {
+    let env = TestEnv::new();
+    let work = env.create_list(alice(), "  Work ");
+    assert_eq!(work.name, "Work");
+    assert_eq!(work.colour, DEFAULT_LIST_COLOUR);
+    assert!(!work.archived);
+    let home = env.create_list(alice(), "Home");
+    env.create_list(bob(), "Bob's list");
+
+    let renamed = env.update_list(
+        alice(),
+        UpdateListInput {
+            id: home.id,
+            name: Some("Household".to_string()),
+            colour: Some("#FF8800".to_string()),
+            archived: Some(true),
+        },
+    );
+    assert!(matches!(
+        renamed,
+        ListResult::Ok(list) if list.name == "Household" && list.colour == "#ff8800" && list.archived
+    ));
+
+    let names = |lists: Vec<TodoList>| lists.into_iter().map(|l| l.name).collect::<Vec<_>>();
+    assert_eq!(names(env.get_lists(alice(), false)), vec!["Work"]);
+    assert_eq!(
+        names(env.get_lists(alice(), true)),
+        vec!["Work", "Household"]
+    );
+
+    let bad_colour = env.update_list(
+        alice(),
+        UpdateListInput {
+            id: work.id,
+            colour: Some("red".to_string()),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        bad_colour,
+        ListResult::Err(TodoError::validation("colour", "must look like #4c51bf"))
+    );
+    assert_eq!(
+        env.update_list(
+            bob(),
+            UpdateListInput {
+                id: work.id,
+                name: Some("Mine".to_string()),
+                ..Default::default()
+            }
+        ),
+        ListResult::Err(TodoError::Unauthorized)
+    );
+    assert_eq!(
+        env.delete_list(bob(), work.id),
+        DeleteResult::Err(TodoError::Unauthorized)
+    );
+}
+
+#[test]
+fn test_todos_filtered_and_moved_between_lists() {
+    let env = TestEnv::new();
+    let work = env.create_list(alice(), "Work");
+    let home = env.create_list(alice(), "Home");
+    let in_list = |list_id: u64, text: &str| CreateTodoInput {
+        text: text.to_string(),
+        list_id: Some(list_id),
+        ..Default::default()
+    };
+    let report = env.create_todo_with(alice(), in_list(work.id, "Report"));
+    let laundry = env.create_todo_with(alice(), in_list(home.id, "Laundry"));
+    env.create_sample_todo(alice(), "Unfiled");
+
+    assert_eq!(env.list_todo_ids(alice(), work.id), vec![report.id]);
+    assert_eq!(env.list_todo_ids(alice(), home.id), vec![laundry.id]);
+    assert!(env.list_todo_ids(bob(), work.id).is_empty());
+
+    env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: laundry.id,
+            list_id: Some(Some(work.id)),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        env.list_todo_ids(alice(), work.id),
+        vec![laundry.id, report.id]
+    );
+    assert!(env.list_todo_ids(alice(), home.id).is_empty());
+}
+
+#[test]
+fn test_todos_only_go_into_own_unarchived_lists() {
+    let env = TestEnv::new();
+    let bobs = env.create_list(bob(), "Bob's");
+    let archived = env.create_list(alice(), "Old");
+    env.update_list(
+        alice(),
+        UpdateListInput {
+            id: archived.id,
+            archived: Some(true),
+            ..Default::default()
+        },
+    );
+
+    let add_to = |list_id: u64| -> TodoResult {
+        let input = CreateTodoInput {
+            text: "Sneaky".to_string(),
+            list_id: Some(list_id),
+            ..Default::default()
         };
+        env.update(alice(), "add_todo", encode_one(input).unwrap())
+    };
+    assert_eq!(
+        add_to(bobs.id),
+        TodoResult::Err(TodoError::validation(
+            "list_id",
+            "does not refer to one of your lists"
+        ))
+    );
+    assert_eq!(
+        add_to(archived.id),
+        TodoResult::Err(TodoError::validation("list_id", "is archived"))
+    );
+}
 
-        let result = service.get_all_todos(pagination);
+#[test]
+fn test_deleting_a_list_moves_its_todos_to_the_trash() {
+    let env = TestEnv::new();
+    let list = env.create_list(alice(), "Trip");
+    let input = CreateTodoInput {
+        text: "Pack".to_string(),
+        list_id: Some(list.id),
+        ..Default::default()
+    };
+    let packed = env.create_todo_with(alice(), input);
+    let kept = env.create_sample_todo(alice(), "Water plants");
+
+    assert_eq!(env.delete_list(alice(), list.id), DeleteResult::Ok(true));
+    assert!(env.get_lists(alice(), true).is_empty());
+    assert_eq!(env.get_todo_count(alice()), 1);
+    assert!(matches!(env.get_todo(alice(), kept.id), TodoResult::Ok(_)));
+    assert_eq!(env.list_trash(alice()).items[0].todo.id, packed.id);
+
+    // The list is gone, so the restored todo comes back unfiled.
+    match env.restore_todo(alice(), packed.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.list_id, None),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    assert_eq!(
+        env.delete_list(alice(), list.id),
+        DeleteResult::Err(TodoError::NotFound { id: list.id })
+    );
+}
 
-        match result {
-            TodosResult::Ok(page) => {
-                assert_eq!(page.todos.len(), 0);
-                assert_eq!(page.total_count, 0);
-                assert!(!page.has_more);
+#[test]
+fn test_viewers_read_and_editors_change_shared_lists() {
+    let env = TestEnv::new();
+    let list = env.create_list(alice(), "Team");
+    let input = CreateTodoInput {
+        text: "Plan sprint".to_string(),
+        list_id: Some(list.id),
+        ..Default::default()
+    };
+    let todo = env.create_todo_with(alice(), input.clone());
+    env.share_list(alice(), list.id, bob(), ListRole::Viewer);
+    env.share_list(alice(), list.id, carol(), ListRole::Editor);
+
+    assert_eq!(env.list_todo_ids(bob(), list.id), vec![todo.id]);
+    assert!(matches!(env.get_todo(bob(), todo.id), TodoResult::Ok(_)));
+    assert_eq!(
+        env.update_todo_text(bob(), todo.id, "Skip sprint"),
+        TodoResult::Err(TodoError::Unauthorized)
+    );
+    assert_eq!(
+        env.update::<TodoResult>(bob(), "add_todo", encode_one(input.clone()).unwrap()),
+        TodoResult::Err(TodoError::validation(
+            "list_id",
+            "is shared with you read-only"
+        ))
+    );
+
+    assert!(matches!(
+        env.update_todo_text(carol(), todo.id, "Plan sprint 12"),
+        TodoResult::Ok(_)
+    ));
+    // Todos an editor adds belong to the list's creator.
+    let added = env.create_todo_with(carol(), input);
+    assert_eq!(added.owner, alice());
+    assert_eq!(env.get_todo_count(alice()), 2);
+    assert_eq!(env.get_todo_count(carol()), 0);
+    assert_eq!(env.delete_todo(carol(), added.id), DeleteResult::Ok(true));
+    assert_eq!(
+        env.update_list(
+            carol(),
+            UpdateListInput {
+                id: list.id,
+                name: Some("Carol's".to_string()),
+                ..Default::default()
             }
-            TodosResult::Err(e) => panic!("Expected success, got error: {}", e),
+        ),
+        ListResult::Err(TodoError::Unauthorized)
+    );
+
+    let shared = env.list_shared_with_me(carol());
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0].list.id, list.id);
+    assert_eq!(shared[0].role, ListRole::Editor);
+}
+
+#[test]
+fn test_revoked_share_stops_working_on_the_next_call() {
+    let env = TestEnv::new();
+    let list = env.create_list(alice(), "Team");
+    let todo = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "Retro".to_string(),
+            list_id: Some(list.id),
+            ..Default::default()
+        },
+    );
+    env.share_list(alice(), list.id, bob(), ListRole::Editor);
+    assert!(matches!(env.get_todo(bob(), todo.id), TodoResult::Ok(_)));
+
+    assert_eq!(
+        env.revoke_share(alice(), list.id, bob()),
+        DeleteResult::Ok(true)
+    );
+    assert_eq!(
+        env.get_todo(bob(), todo.id),
+        TodoResult::Err(TodoError::NotFound { id: todo.id })
+    );
+    assert_eq!(
+        env.update_todo_text(bob(), todo.id, "Too late"),
+        TodoResult::Err(TodoError::Unauthorized)
+    );
+    assert!(env.list_todo_ids(bob(), list.id).is_empty());
+    assert!(env.list_shared_with_me(bob()).is_empty());
+
+    // Anyone can leave a list shared with them.
+    env.share_list(alice(), list.id, carol(), ListRole::Viewer);
+    assert_eq!(
+        env.revoke_share(carol(), list.id, carol()),
+        DeleteResult::Ok(true)
+    );
+    assert_eq!(
+        env.revoke_share(carol(), list.id, alice()),
+        DeleteResult::Err(TodoError::Unauthorized)
+    );
+}
+
+#[test]
+fn test_only_owners_manage_shares() {
+    let env = TestEnv::new();
+    let list = env.create_list(alice(), "Team");
+
+    assert_eq!(
+        env.share_list(bob(), list.id, carol(), ListRole::Owner),
+        ShareResult::Err(TodoError::Unauthorized)
+    );
+    assert_eq!(
+        env.share_list(alice(), list.id, alice(), ListRole::Viewer),
+        ShareResult::Err(TodoError::validation("principal", "already owns the list"))
+    );
+
+    env.share_list(alice(), list.id, bob(), ListRole::Owner);
+    assert!(matches!(
+        env.share_list(bob(), list.id, carol(), ListRole::Viewer),
+        ShareResult::Ok(_)
+    ));
+    match env.get_list_shares(carol(), list.id) {
+        SharesResult::Ok(shares) => {
+            let roles: Vec<(Principal, ListRole)> = shares
+                .iter()
+                .map(|share| (share.principal, share.role))
+                .collect();
+            assert_eq!(
+                roles,
+                vec![
+                    (alice(), ListRole::Owner),
+                    (bob(), ListRole::Owner),
+                    (carol(), ListRole::Viewer),
+                ]
+            );
         }
+        SharesResult::Err(e) => panic!("Expected shares, got error: {:?}", e),
     }
 
-    #[test]
-    fn test_pagination_limit_zero() {
-        let service = create_test_service();
-        let pagination = PaginationInput {
-            offset: 0,
-            limit: 0,
-        };
+    assert_eq!(env.delete_list(bob(), list.id), DeleteResult::Ok(true));
+    assert!(env.list_shared_with_me(carol()).is_empty());
+    assert_eq!(
+        env.get_list_shares(alice(), list.id),
+        SharesResult::Err(TodoError::NotFound { id: list.id })
+    );
+}
+
+#[test]
+fn test_subtasks_and_completion_percent() {
+    let env = TestEnv::new();
+    let list = env.create_list(alice(), "Move");
+    let input = CreateTodoInput {
+        text: "Move house".to_string(),
+        list_id: Some(list.id),
+        ..Default::default()
+    };
+    let parent = env.create_todo_with(alice(), input);
+    let boxes = env.create_subtask(alice(), parent.id, "Buy boxes");
+    let pack = env.create_subtask(alice(), parent.id, "Pack");
+    let books = env.create_subtask(alice(), pack.id, "Pack books");
+    let dishes = env.create_subtask(alice(), pack.id, "Pack dishes");
+
+    assert_eq!(boxes.parent_id, Some(parent.id));
+    assert_eq!(boxes.list_id, Some(list.id));
+
+    let tree = env.get_todo_with_subtasks(alice(), parent.id);
+    let ids: Vec<u64> = tree.subtasks.iter().map(|t| t.id).collect();
+    assert_eq!(ids, vec![boxes.id, pack.id]);
+    assert_eq!(tree.completion_percent, 0);
+
+    env.update_todo_completed(alice(), boxes.id, true);
+    env.update_todo_completed(alice(), books.id, true);
+    assert_eq!(
+        env.get_todo_with_subtasks(alice(), parent.id)
+            .completion_percent,
+        50
+    );
+    assert_eq!(
+        env.get_todo_with_subtasks(alice(), pack.id)
+            .completion_percent,
+        50
+    );
+    assert_eq!(
+        env.get_todo_with_subtasks(alice(), dishes.id)
+            .completion_percent,
+        0
+    );
+    assert_eq!(
+        env.get_todo_with_subtasks(alice(), books.id)
+            .completion_percent,
+        100
+    );
+
+    assert_eq!(
+        env.add_subtask(bob(), parent.id, "Sneaky"),
+        TodoResult::Err(TodoError::validation(
+            "parent_id",
+            "does not refer to one of your todos"
+        ))
+    );
+    let result: SubtasksResult = env.query(
+        bob(),
+        "get_todo_with_subtasks",
+        encode_one(parent.id).unwrap(),
+    );
+    assert_eq!(
+        result,
+        SubtasksResult::Err(TodoError::NotFound { id: parent.id })
+    );
+}
+
+#[test]
+fn test_move_subtree_keeps_children_and_rejects_cycles() {
+    let env = TestEnv::new();
+    let home = env.create_sample_todo(alice(), "Home");
+    let garden = env.create_sample_todo(alice(), "Garden");
+    let shed = env.create_subtask(alice(), home.id, "Fix shed");
+    let roof = env.create_subtask(alice(), shed.id, "Fix roof");
+
+    match env.move_subtree(alice(), shed.id, Some(garden.id)) {
+        TodoResult::Ok(todo) => assert_eq!(todo.parent_id, Some(garden.id)),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    assert!(env
+        .get_todo_with_subtasks(alice(), home.id)
+        .subtasks
+        .is_empty());
+    let garden_tree = env.get_todo_with_subtasks(alice(), garden.id);
+    assert_eq!(garden_tree.subtasks[0].id, shed.id);
+    assert_eq!(
+        env.get_todo_with_subtasks(alice(), shed.id).subtasks[0].id,
+        roof.id
+    );
+
+    let cycle = TodoResult::Err(TodoError::validation(
+        "parent_id",
+        "cannot be the todo itself or one of its subtasks",
+    ));
+    assert_eq!(env.move_subtree(alice(), shed.id, Some(roof.id)), cycle);
+    assert_eq!(env.move_subtree(alice(), shed.id, Some(shed.id)), cycle);
+    assert_eq!(
+        env.move_subtree(bob(), shed.id, None),
+        TodoResult::Err(TodoError::Unauthorized)
+    );
+
+    match env.move_subtree(alice(), shed.id, None) {
+        TodoResult::Ok(todo) => assert_eq!(todo.parent_id, None),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+}
+
+#[test]
+fn test_moving_a_parent_to_another_list_takes_its_subtasks() {
+    let env = TestEnv::new();
+    let home = env.create_list(alice(), "Home");
+    let work = env.create_list(alice(), "Work");
+    let parent = env.create_sample_todo(alice(), "Plan move");
+    env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: parent.id,
+            list_id: Some(Some(home.id)),
+            ..Default::default()
+        },
+    );
+    let child = env.create_subtask(alice(), parent.id, "Book van");
+    let grandchild = env.create_subtask(alice(), child.id, "Compare prices");
+
+    match env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: parent.id,
+            list_id: Some(Some(work.id)),
+            ..Default::default()
+        },
+    ) {
+        TodoResult::Ok(todo) => assert_eq!(todo.list_id, Some(work.id)),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    for id in [child.id, grandchild.id] {
+        match env.get_todo(alice(), id) {
+            TodoResult::Ok(todo) => assert_eq!(todo.list_id, Some(work.id)),
+            TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+        }
+    }
+}
 
-        let result = service.get_all_todos(pagination);
+#[test]
+fn test_subtasks_cannot_leave_their_parents_list() {
+    let env = TestEnv::new();
+    let home = env.create_list(alice(), "Home");
+    let work = env.create_list(alice(), "Work");
+    let parent = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "Plan move".to_string(),
+            list_id: Some(home.id),
+            ..Default::default()
+        },
+    );
+    let child = env.create_subtask(alice(), parent.id, "Book van");
+    let list_error = TodoError::validation("list_id", "must be the list of the parent todo");
+
+    let elsewhere = CreateTodoInput {
+        text: "Pack boxes".to_string(),
+        list_id: Some(work.id),
+        ..Default::default()
+    };
+    let added: TodoResult = env.update(
+        alice(),
+        "add_subtask",
+        encode_args((parent.id, elsewhere)).unwrap(),
+    );
+    assert_eq!(added, TodoResult::Err(list_error.clone()));
+
+    let moved = env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: child.id,
+            list_id: Some(Some(work.id)),
+            ..Default::default()
+        },
+    );
+    assert_eq!(moved, TodoResult::Err(list_error));
+    match env.get_todo(alice(), child.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.list_id, Some(home.id)),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+}
 
-        match result {
-            TodosResult::Ok(_) => panic!("Expected error for limit 0"),
-            TodosResult::Err(e) => assert_eq!(e, "Limit must be greater than 0"),
+#[test]
+fn test_moving_a_subtree_under_a_parent_in_another_list_moves_it_there() {
+    let env = TestEnv::new();
+    let work = env.create_list(alice(), "Work");
+    let parent = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "Quarterly review".to_string(),
+            list_id: Some(work.id),
+            ..Default::default()
+        },
+    );
+    let loose = env.create_sample_todo(alice(), "Collect numbers");
+    let child = env.create_subtask(alice(), loose.id, "Ask finance");
+
+    match env.move_subtree(alice(), loose.id, Some(parent.id)) {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.parent_id, Some(parent.id));
+            assert_eq!(todo.list_id, Some(work.id));
         }
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    match env.get_todo(alice(), child.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.list_id, Some(work.id)),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
 
-    #[test]
-    fn test_update_todo_text() {
-        let mut service = create_test_service();
-        let created_todo = create_sample_todo(&mut service, "Original text");
+    // Back at the top level, the subtree stays where it is.
+    match env.move_subtree(alice(), loose.id, None) {
+        TodoResult::Ok(todo) => assert_eq!(todo.list_id, Some(work.id)),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+}
 
-        let input = UpdateTodoInput {
-            id: created_todo.id,
-            text: Some("Updated text".to_string()),
-            completed: None,
-        };
+#[test]
+fn test_subtask_depth_is_limited() {
+    let env = TestEnv::new();
+    let mut parent = env.create_sample_todo(alice(), "Level 0");
+    for level in 1..=MAX_SUBTASK_DEPTH {
+        parent = env.create_subtask(alice(), parent.id, &format!("Level {}", level));
+    }
 
-        let result = service.update_todo(input);
+    assert!(matches!(
+        env.add_subtask(alice(), parent.id, "Too deep"),
+        TodoResult::Err(TodoError::Validation { field, .. }) if field == "parent_id"
+    ));
+}
 
-        match result {
-            TodoResult::Ok(todo) => {
-                assert_eq!(todo.text, "Updated text");
-                assert!(todo.updated_at >= created_todo.updated_at);
-            }
-            TodoResult::Err(e) => panic!("Expected success, got error: {}", e),
-        }
+#[test]
+fn test_deleting_a_parent_trashes_its_subtasks() {
+    let env = TestEnv::new();
+    let parent = env.create_sample_todo(alice(), "Trip");
+    let pack = env.create_subtask(alice(), parent.id, "Pack");
+    let socks = env.create_subtask(alice(), pack.id, "Socks");
+    env.create_sample_todo(alice(), "Unrelated");
+
+    assert_eq!(env.delete_todo(alice(), parent.id), DeleteResult::Ok(true));
+    assert_eq!(env.get_todo_count(alice()), 1);
+    assert_eq!(env.list_trash(alice()).total_count, 3);
+
+    // Restoring the parent brings the whole tree back.
+    match env.restore_todo(alice(), parent.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.parent_id, None),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    assert_eq!(env.get_todo_count(alice()), 4);
+    assert_eq!(env.list_trash(alice()).total_count, 0);
+    let tree = env.get_todo_with_subtasks(alice(), parent.id);
+    let ids: Vec<u64> = tree.subtasks.iter().map(|t| t.id).collect();
+    assert_eq!(ids, vec![pack.id]);
+    let tree = env.get_todo_with_subtasks(alice(), pack.id);
+    let ids: Vec<u64> = tree.subtasks.iter().map(|t| t.id).collect();
+    assert_eq!(ids, vec![socks.id]);
+
+    // Restored before its parent, a subtask comes back at the top level,
+    // still with its own subtasks.
+    assert_eq!(env.delete_todo(alice(), parent.id), DeleteResult::Ok(true));
+    match env.restore_todo(alice(), pack.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.parent_id, None),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    match env.get_todo(alice(), socks.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.parent_id, Some(pack.id)),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+    assert_eq!(env.list_trash(alice()).total_count, 1);
+}
 
-    #[test]
-    fn test_delete_todo_success() {
-        let mut service = create_test_service();
-        let created_todo = create_sample_todo(&mut service, "To be deleted");
+#[test]
+fn test_move_todo_sets_the_user_order() {
+    let env = TestEnv::new();
+    let a = env.create_sample_todo(alice(), "A");
+    let b = env.create_sample_todo(alice(), "B");
+    let c = env.create_sample_todo(alice(), "C");
 
-        let result = service.delete_todo(created_todo.id);
+    // New todos go to the top.
+    assert_eq!(env.ids_in_user_order(alice(), None), vec![c.id, b.id, a.id]);
 
-        match result {
-            DeleteResult::Ok(deleted) => {
-                assert!(deleted);
-                match service.get_todo(created_todo.id) {
-                    TodoResult::Err(e) => assert_eq!(e, "Todo not found"),
-                    TodoResult::Ok(_) => panic!("Todo should be deleted"),
-                }
-            }
-            DeleteResult::Err(e) => panic!("Expected success, got error: {}", e),
-        }
+    match env.move_todo(alice(), a.id, Some(c.id), Some(b.id)) {
+        TodoResult::Ok(todo) => assert_eq!(todo.updated_at, a.updated_at),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+    assert_eq!(env.ids_in_user_order(alice(), None), vec![c.id, a.id, b.id]);
 
-    #[test]
-    fn test_delete_todo_not_found() {
-        let mut service = create_test_service();
+    env.move_todo(alice(), c.id, Some(b.id), None);
+    assert_eq!(env.ids_in_user_order(alice(), None), vec![a.id, b.id, c.id]);
 
-        let result = service.delete_todo(999);
+    env.move_todo(alice(), c.id, None, Some(a.id));
+    assert_eq!(env.ids_in_user_order(alice(), None), vec![c.id, a.id, b.id]);
 
-        match result {
-            DeleteResult::Ok(_) => panic!("Expected error for non-existent todo"),
-            DeleteResult::Err(e) => assert_eq!(e, "Todo not found"),
-        }
+    // Only the moved todo's rank changes.
+    match env.get_todo(alice(), b.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.rank, b.rank),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
     }
+}
 
-    #[test]
-    fn test_crud_workflow() {
-        let mut service = create_test_service();
+#[test]
+fn test_move_todo_orders_within_a_list() {
+    let env = TestEnv::new();
+    let list = env.create_list(alice(), "Work");
+    let in_list = |text: &str| CreateTodoInput {
+        text: text.to_string(),
+        list_id: Some(list.id),
+        ..Default::default()
+    };
+    let first = env.create_todo_with(alice(), in_list("First"));
+    let second = env.create_todo_with(alice(), in_list("Second"));
+    let unfiled = env.create_sample_todo(alice(), "Unfiled");
+
+    env.move_todo(alice(), first.id, None, Some(second.id));
+    assert_eq!(
+        env.ids_in_user_order(alice(), Some(list.id)),
+        vec![first.id, second.id]
+    );
+
+    assert_eq!(
+        env.move_todo(alice(), first.id, Some(unfiled.id), None),
+        TodoResult::Err(TodoError::validation(
+            "before",
+            "must be another todo in the same list"
+        ))
+    );
+    assert_eq!(
+        env.move_todo(alice(), first.id, None, None),
+        TodoResult::Err(TodoError::validation(
+            "before",
+            "either before or after is required"
+        ))
+    );
+    assert_eq!(
+        env.move_todo(bob(), first.id, None, Some(second.id)),
+        TodoResult::Err(TodoError::Unauthorized)
+    );
+
+    // Moving a todo into the list puts it at the top.
+    env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: unfiled.id,
+            list_id: Some(Some(list.id)),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        env.ids_in_user_order(alice(), Some(list.id)),
+        vec![unfiled.id, first.id, second.id]
+    );
+}
 
-        let todo = create_sample_todo(&mut service, "Learn Rust");
-        assert_eq!(todo.text, "Learn Rust");
-        assert!(!todo.completed);
+#[test]
+fn test_completing_a_recurring_todo_spawns_the_next_occurrence() {
+    let env = TestEnv::new();
+    let day = 24 * 60 * 60 * 1_000_000_000;
+    let due_at = env.now() + day;
+    let report = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "Weekly report".to_string(),
+            due_at: Some(due_at),
+            tags: Some(vec!["work".to_string()]),
+            recurrence: Some(Recurrence::EveryNDays { days: 7 }),
+            ..Default::default()
+        },
+    );
+
+    match env.update_todo_completed(alice(), report.id, true) {
+        TodoResult::Ok(todo) => {
+            assert!(todo.completed);
+            assert_eq!(todo.recurrence, None);
+        }
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    let next = match env.get_todo(alice(), report.id + 1) {
+        TodoResult::Ok(todo) => todo,
+        TodoResult::Err(e) => panic!("Expected the next occurrence: {:?}", e),
+    };
+    assert_eq!(next.text, "Weekly report");
+    assert!(!next.completed);
+    assert_eq!(next.due_at, Some(due_at + 7 * day));
+    assert_eq!(next.tags, vec!["work"]);
+    assert_eq!(next.recurrence, Some(Recurrence::EveryNDays { days: 7 }));
+
+    // The rule moved on, so completing the old occurrence again adds nothing.
+    env.update_todo_completed(alice(), report.id, false);
+    env.update_todo_completed(alice(), report.id, true);
+    assert_eq!(env.get_todo_count(alice()), 2);
+}
+
+#[test]
+fn test_recurring_series_ends_at_the_owners_quota() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let config = Config {
+        max_todos_per_owner: 1,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(alice(), "set_config", encode_one(config.clone()).unwrap());
+    assert_eq!(set, ConfigResult::Ok(config));
+    let report = env.create_todo_with(
+        bob(),
+        CreateTodoInput {
+            text: "Weekly report".to_string(),
+            due_at: Some(env.now() + 24 * 60 * 60 * 1_000_000_000),
+            recurrence: Some(Recurrence::Weekly {
+                weekdays: vec![Weekday::Monday],
+            }),
+            ..Default::default()
+        },
+    );
+
+    match env.update_todo_completed(bob(), report.id, true) {
+        TodoResult::Ok(todo) => assert!(todo.completed),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    assert_eq!(env.get_todo_count(bob()), 1);
+}
 
-        match service.get_todo(todo.id) {
-            TodoResult::Ok(retrieved) => assert_eq!(retrieved.id, todo.id),
-            TodoResult::Err(e) => panic!("Should retrieve todo: {}", e),
+#[test]
+fn test_invalid_recurrence_is_rejected() {
+    let env = TestEnv::new();
+    let recurring = |due_at: Option<u64>, recurrence: Recurrence| CreateTodoInput {
+        text: "Chore".to_string(),
+        due_at,
+        recurrence: Some(recurrence),
+        ..Default::default()
+    };
+    let invalid = [
+        recurring(None, Recurrence::Daily),
+        recurring(Some(1), Recurrence::Weekly { weekdays: vec![] }),
+        recurring(
+            Some(1),
+            Recurrence::Weekly {
+                weekdays: vec![Weekday::Monday, Weekday::Monday],
+            },
+        ),
+        recurring(Some(1), Recurrence::Monthly { day: 32 }),
+        recurring(Some(1), Recurrence::EveryNDays { days: 0 }),
+    ];
+
+    for input in invalid {
+        match env.update::<TodoResult>(alice(), "add_todo", encode_one(input).unwrap()) {
+            TodoResult::Err(TodoError::Validation { field, .. }) => assert_eq!(field, "recurrence"),
+            other => panic!("Expected a recurrence validation error, got {:?}", other),
         }
+    }
 
-        let update_input = UpdateTodoInput {
+    let todo = env.create_sample_todo(alice(), "No due date");
+    let result = env.update_todo(
+        alice(),
+        UpdateTodoInput {
             id: todo.id,
-            text: Some("Master Rust".to_string()),
-            completed: Some(true),
-        };
+            recurrence: Some(Some(Recurrence::Daily)),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result,
+        TodoResult::Err(TodoError::validation("recurrence", "requires a due date"))
+    );
+}
 
-        match service.update_todo(update_input) {
-            TodoResult::Ok(updated) => {
-                assert_eq!(updated.text, "Master Rust");
-                assert!(updated.completed);
-            }
-            TodoResult::Err(e) => panic!("Should update todo: {}", e),
+#[test]
+fn test_timer_creates_upcoming_occurrences() {
+    let env = TestEnv::new();
+    let hour = 60 * 60 * 1_000_000_000;
+    let due_at = env.now() + hour;
+    let standup = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "Standup notes".to_string(),
+            due_at: Some(due_at),
+            recurrence: Some(Recurrence::Daily),
+            ..Default::default()
+        },
+    );
+
+    // Tomorrow's occurrence comes within a day; let the hourly timer run.
+    env.pic.advance_time(Duration::from_secs(2 * 60 * 60));
+    env.pic.tick();
+    env.pic.tick();
+
+    assert_eq!(env.get_todo_count(alice()), 2);
+    match env.get_todo(alice(), standup.id) {
+        TodoResult::Ok(todo) => {
+            assert!(!todo.completed);
+            assert_eq!(todo.recurrence, None);
+        }
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    match env.get_todo(alice(), standup.id + 1) {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.due_at, Some(due_at + 24 * hour));
+            assert_eq!(todo.recurrence, Some(Recurrence::Daily));
+        }
+        TodoResult::Err(e) => panic!("Expected the next occurrence: {:?}", e),
+    }
+
+    // Completing the earlier occurrence does not add a third.
+    env.update_todo_completed(alice(), standup.id, true);
+    assert_eq!(env.get_todo_count(alice()), 2);
+}
+
+#[test]
+fn test_due_reminders_are_delivered_to_the_owners_inbox() {
+    let env = TestEnv::new();
+    let remind_at = env.now() + 30 * 1_000_000_000;
+    let todo = env.create_todo_with(
+        alice(),
+        CreateTodoInput {
+            text: "Call the plumber".to_string(),
+            remind_at: Some(remind_at),
+            ..Default::default()
+        },
+    );
+    assert!(env.get_notifications(alice()).is_empty());
+
+    // Let the reminder timer run past `remind_at`.
+    env.pic.advance_time(Duration::from_secs(2 * 60));
+    env.pic.tick();
+    env.pic.tick();
+
+    let inbox = env.get_notifications(alice());
+    assert_eq!(inbox.len(), 1);
+    assert_eq!(inbox[0].todo_id, todo.id);
+    assert_eq!(inbox[0].text, "Call the plumber");
+    assert_eq!(inbox[0].remind_at, remind_at);
+    match env.get_todo(alice(), todo.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.remind_at, None),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+
+    assert!(env.get_notifications(bob()).is_empty());
+    assert_eq!(env.ack_notifications(bob(), vec![inbox[0].id]), 0);
+    assert_eq!(env.ack_notifications(alice(), vec![inbox[0].id]), 1);
+    assert!(env.get_notifications(alice()).is_empty());
+}
+
+#[test]
+fn test_completed_and_cleared_reminders_are_not_delivered() {
+    let env = TestEnv::new();
+    let reminder = |text: &str| CreateTodoInput {
+        text: text.to_string(),
+        remind_at: Some(env.now() + 30 * 1_000_000_000),
+        ..Default::default()
+    };
+    let done = env.create_todo_with(alice(), reminder("Already done"));
+    let cleared = env.create_todo_with(alice(), reminder("Never mind"));
+    env.update_todo_completed(alice(), done.id, true);
+    env.update_todo(
+        alice(),
+        UpdateTodoInput {
+            id: cleared.id,
+            remind_at: Some(None),
+            ..Default::default()
+        },
+    );
+
+    env.pic.advance_time(Duration::from_secs(2 * 60));
+    env.pic.tick();
+    env.pic.tick();
+
+    assert!(env.get_notifications(alice()).is_empty());
+}
+
+#[test]
+fn test_crud_workflow() {
+    let env = TestEnv::new();
+
+    let todo = env.create_sample_todo(alice(), "Learn Rust");
+
+    match env.update_todo_text(alice(), todo.id, "Master Rust") {
+        TodoResult::Ok(updated) => assert_eq!(updated.text, "Master Rust"),
+        TodoResult::Err(e) => panic!("Should update todo text: {:?}", e),
+    }
+
+    match env.update_todo_completed(alice(), todo.id, true) {
+        TodoResult::Ok(updated) => {
+            assert_eq!(updated.text, "Master Rust");
+            assert!(updated.completed);
         }
+        TodoResult::Err(e) => panic!("Should complete todo: {:?}", e),
+    }
+
+    assert_eq!(env.delete_todo(alice(), todo.id), DeleteResult::Ok(true));
 
-        match service.delete_todo(todo.id) {
-            DeleteResult::Ok(_) => {}
-            DeleteResult::Err(e) => panic!("Should delete todo: {}", e),
+    match env.get_todo(alice(), todo.id) {
+        TodoResult::Err(e) => assert_eq!(e, TodoError::NotFound { id: todo.id }),
+        TodoResult::Ok(_) => panic!("Todo should be deleted"),
+    }
+}
+
+#[test]
+fn test_batch_create_reports_each_item() {
+    let env = TestEnv::new();
+
+    let results = match env.add_todos(alice(), &["First", "   ", "Third"]) {
+        TodoBatchResult::Ok(results) => results,
+        TodoBatchResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    };
+
+    assert_eq!(results.len(), 3);
+    assert!(matches!(&results[0], TodoResult::Ok(todo) if todo.text == "First"));
+    assert_eq!(
+        results[1],
+        TodoResult::Err(TodoError::validation("text", "cannot be empty"))
+    );
+    assert!(matches!(&results[2], TodoResult::Ok(todo) if todo.text == "Third"));
+    assert_eq!(env.get_todo_count(alice()), 2);
+}
+
+#[test]
+fn test_batch_update_and_delete() {
+    let env = TestEnv::new();
+    let first = env.create_sample_todo(alice(), "First");
+    let second = env.create_sample_todo(alice(), "Second");
+    let bobs = env.create_sample_todo(bob(), "Bob's");
+
+    let results = match env.update_todos(
+        alice(),
+        vec![
+            UpdateTodoInput {
+                id: first.id,
+                text: None,
+                completed: Some(true),
+                ..Default::default()
+            },
+            UpdateTodoInput {
+                id: bobs.id,
+                text: None,
+                completed: Some(true),
+                ..Default::default()
+            },
+        ],
+    ) {
+        TodoBatchResult::Ok(results) => results,
+        TodoBatchResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    };
+    assert!(matches!(&results[0], TodoResult::Ok(todo) if todo.completed));
+    assert_eq!(results[1], TodoResult::Err(TodoError::Unauthorized));
+
+    assert_eq!(
+        env.delete_todos(alice(), vec![first.id, second.id, 999]),
+        DeleteBatchResult::Ok(vec![
+            DeleteResult::Ok(true),
+            DeleteResult::Ok(true),
+            DeleteResult::Err(TodoError::NotFound { id: 999 }),
+        ])
+    );
+    assert_eq!(env.get_todo_count(alice()), 0);
+    assert_eq!(env.get_todo_count(bob()), 1);
+}
+
+#[test]
+fn test_batch_size_limit() {
+    let env = TestEnv::new();
+    let texts = vec!["Item"; 101];
+
+    assert_eq!(
+        env.add_todos(alice(), &texts),
+        TodoBatchResult::Err(TodoError::validation(
+            "batch",
+            "cannot contain more than 100 items"
+        ))
+    );
+    assert_eq!(env.get_todo_count(alice()), 0);
+}
+
+#[test]
+fn test_controllers_change_the_batch_size_limit() {
+    let env = TestEnv::new();
+    let config = Config {
+        max_batch_size: 2,
+        ..Config::default()
+    };
+    let message = env.rejection(alice(), "set_config", encode_one(config.clone()).unwrap());
+    assert!(message.contains("not an admin"));
+
+    let set: ConfigResult = env.update(
+        controller(),
+        "set_config",
+        encode_one(config.clone()).unwrap(),
+    );
+    assert_eq!(set, ConfigResult::Ok(config.clone()));
+    let stored: Config = env.query(controller(), "get_config", encode_args(()).unwrap());
+    assert_eq!(stored, config);
+
+    assert_eq!(
+        env.add_todos(alice(), &["One", "Two", "Three"]),
+        TodoBatchResult::Err(TodoError::validation(
+            "batch",
+            "cannot contain more than 2 items"
+        ))
+    );
+    assert!(matches!(
+        env.add_todos(alice(), &["One", "Two"]),
+        TodoBatchResult::Ok(_)
+    ));
+}
+
+#[test]
+fn test_get_todo_count() {
+    let env = TestEnv::new();
+    assert_eq!(env.get_todo_count(alice()), 0);
+
+    let first = env.create_sample_todo(alice(), "First");
+    assert_eq!(env.get_todo_count(alice()), 1);
+
+    env.create_sample_todo(alice(), "Second");
+    assert_eq!(env.get_todo_count(alice()), 2);
+
+    env.delete_todo(alice(), first.id);
+    assert_eq!(env.get_todo_count(alice()), 1);
+}
+
+#[test]
+fn test_todos_are_scoped_to_owner() {
+    let env = TestEnv::new();
+    env.create_sample_todo(alice(), "Alice's todo");
+    let bobs_todo = env.create_sample_todo(bob(), "Bob's todo");
+
+    assert_eq!(env.get_todo_count(alice()), 1);
+    assert_eq!(env.get_todo_count(bob()), 1);
+
+    match env.get_all_todos(alice(), 0, 10) {
+        TodosResult::Ok(page) => {
+            assert_eq!(page.total_count, 1);
+            assert!(page.todos.iter().all(|todo| todo.owner == alice()));
         }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+
+    match env.get_todo(alice(), bobs_todo.id) {
+        TodoResult::Ok(_) => panic!("Alice should not see Bob's todo"),
+        TodoResult::Err(e) => assert_eq!(e, TodoError::NotFound { id: bobs_todo.id }),
+    }
+}
+
+#[test]
+fn test_non_owner_cannot_update_or_delete() {
+    let env = TestEnv::new();
+    let todo = env.create_sample_todo(alice(), "Alice's todo");
+
+    assert_eq!(
+        env.update_todo_text(bob(), todo.id, "Hijacked"),
+        TodoResult::Err(TodoError::Unauthorized)
+    );
+    assert_eq!(
+        env.delete_todo(bob(), todo.id),
+        DeleteResult::Err(TodoError::Unauthorized)
+    );
+    assert_eq!(env.get_todo_count(alice()), 1);
+}
+
+#[test]
+fn test_admins_are_seeded_from_init_args_and_guarded() {
+    let env = TestEnv::with_admins(vec![alice()]);
+
+    let admins: Vec<Principal> = env.query(alice(), "list_admins", encode_args(()).unwrap());
+    assert_eq!(admins, vec![alice()]);
+    assert!(env
+        .rejection(bob(), "add_admin", encode_one(carol()).unwrap())
+        .contains("not an admin"));
+
+    let added: bool = env.update(alice(), "add_admin", encode_one(bob()).unwrap());
+    assert!(added);
+    let stats: GlobalStats = env.query(bob(), "get_global_stats", encode_args(()).unwrap());
+    assert_eq!(stats.admins, 2);
+
+    let removed: bool = env.update(alice(), "remove_admin", encode_one(bob()).unwrap());
+    assert!(removed);
+    assert!(env
+        .rejection(
+            bob(),
+            "run_maintenance",
+            encode_one(MaintenanceTask::PurgeExpiredTrash).unwrap()
+        )
+        .contains("not an admin"));
+}
+
+#[test]
+fn test_banned_principals_are_refused_until_unbanned() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    env.create_sample_todo(bob(), "Spam");
+
+    let banned: bool = env.update(alice(), "ban_principal", encode_one(bob()).unwrap());
+    assert!(banned);
+    let add = CreateTodoInput {
+        text: "More spam".to_string(),
+        ..Default::default()
+    };
+    assert!(env
+        .rejection(bob(), "add_todo", encode_one(add).unwrap())
+        .contains("banned"));
+    // Admins cannot be banned.
+    let banned_admin: bool = env.update(alice(), "ban_principal", encode_one(alice()).unwrap());
+    assert!(!banned_admin);
+
+    let unbanned: bool = env.update(alice(), "unban_principal", encode_one(bob()).unwrap());
+    assert!(unbanned);
+    assert_eq!(env.get_todo_count(bob()), 1);
+}
 
-        match service.get_todo(todo.id) {
-            TodoResult::Err(e) => assert_eq!(e, "Todo not found"),
-            TodoResult::Ok(_) => panic!("Todo should be deleted"),
+#[test]
+fn test_admin_config_caps_todos_per_owner() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let config = Config {
+        max_todos_per_owner: 2,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(alice(), "set_config", encode_one(config.clone()).unwrap());
+    assert_eq!(set, ConfigResult::Ok(config.clone()));
+    let stored: Config = env.query(alice(), "get_config", encode_args(()).unwrap());
+    assert_eq!(stored, config);
+
+    env.create_sample_todo(bob(), "One");
+    env.create_sample_todo(bob(), "Two");
+    assert_eq!(
+        env.add_todo(bob(), "Three"),
+        TodoResult::Err(TodoError::QuotaExceeded {
+            quota: "max_todos_per_owner".to_string(),
+            limit: 2,
+        })
+    );
+
+    let stats: GlobalStats = env.query(alice(), "get_global_stats", encode_args(()).unwrap());
+    assert_eq!(stats.todos, 2);
+    assert_eq!(stats.owners, 1);
+    assert!(stats.stable_memory_bytes > 0);
+}
+
+#[test]
+fn test_config_from_upgrade_args_applies_to_validation() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let config = Config {
+        max_todo_text_length: 10,
+        max_page_limit: 5,
+        ..Config::default()
+    };
+    env.upgrade_with(
+        encode_one(Some(InitArgs {
+            config: Some(config),
+            ..Default::default()
+        }))
+        .unwrap(),
+    );
+
+    assert_eq!(
+        env.add_todo(alice(), "Far too long for this canister"),
+        TodoResult::Err(TodoError::validation("text", "cannot exceed 10 characters"))
+    );
+    assert_eq!(
+        env.get_all_todos(alice(), 0, 6),
+        TodosResult::Err(TodoError::validation("limit", "cannot exceed 5"))
+    );
+    assert_eq!(
+        env.search_todos(alice(), "longer than ten"),
+        TodosResult::Err(TodoError::validation(
+            "query",
+            "cannot exceed 10 characters"
+        ))
+    );
+
+    // Text beyond what the stable encoding holds is refused.
+    let oversized = Config {
+        max_todo_text_length: MAX_TODO_TEXT_LENGTH as u64 + 1,
+        ..Config::default()
+    };
+    let result: ConfigResult = env.update(alice(), "set_config", encode_one(oversized).unwrap());
+    assert_eq!(
+        result,
+        ConfigResult::Err(TodoError::validation(
+            "max_todo_text_length",
+            format!("must be between 1 and {}", MAX_TODO_TEXT_LENGTH)
+        ))
+    );
+}
+
+#[test]
+fn test_anonymous_callers_are_refused_by_default() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let add = CreateTodoInput {
+        text: "Spam".to_string(),
+        ..Default::default()
+    };
+    assert!(env
+        .rejection(
+            Principal::anonymous(),
+            "add_todo",
+            encode_one(add.clone()).unwrap()
+        )
+        .contains("anonymous callers are not allowed"));
+
+    let config = Config {
+        allow_anonymous: true,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(alice(), "set_config", encode_one(config).unwrap());
+    assert!(matches!(set, ConfigResult::Ok(_)));
+    let added: TodoResult =
+        env.update(Principal::anonymous(), "add_todo", encode_one(add).unwrap());
+    assert!(matches!(added, TodoResult::Ok(_)));
+}
+
+#[test]
+fn test_updates_are_rate_limited_per_principal() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let config = Config {
+        rate_limit_per_minute: 1,
+        rate_limit_burst: 2,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(alice(), "set_config", encode_one(config).unwrap());
+    assert!(matches!(set, ConfigResult::Ok(_)));
+
+    env.create_sample_todo(bob(), "One");
+    env.create_sample_todo(bob(), "Two");
+    let add = CreateTodoInput {
+        text: "Three".to_string(),
+        ..Default::default()
+    };
+    assert!(env
+        .rejection(bob(), "add_todo", encode_one(add).unwrap())
+        .contains("rate limit exceeded"));
+
+    // Other principals have buckets of their own, and admins have none.
+    env.create_sample_todo(carol(), "Carol's");
+    for text in ["A", "B", "C"] {
+        env.create_sample_todo(alice(), text);
+    }
+
+    env.pic.advance_time(Duration::from_secs(60));
+    env.create_sample_todo(bob(), "Three");
+}
+
+#[test]
+fn test_byte_quota_counts_text_and_tags_of_live_todos() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let config = Config {
+        max_bytes_per_owner: 10,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(alice(), "set_config", encode_one(config).unwrap());
+    assert!(matches!(set, ConfigResult::Ok(_)));
+
+    let over_quota = TodoResult::Err(TodoError::QuotaExceeded {
+        quota: "max_bytes_per_owner".to_string(),
+        limit: 10,
+    });
+    let first = env.create_sample_todo(bob(), "Milk");
+    let tagged = CreateTodoInput {
+        text: "Eggs".to_string(),
+        tags: Some(vec!["shop".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(
+        env.update::<TodoResult>(bob(), "add_todo", encode_one(tagged).unwrap()),
+        over_quota
+    );
+    assert_eq!(
+        env.update_todo_text(bob(), first.id, "Milk and bread"),
+        over_quota
+    );
+
+    // Shrinking is always allowed, and deleting frees the bytes.
+    env.update_todo_text(bob(), first.id, "Tea");
+    env.create_sample_todo(bob(), "Bread");
+    env.delete_todo(bob(), first.id);
+    env.create_sample_todo(bob(), "Jam");
+
+    // A todo in the trash does not count, but restoring it would.
+    env.create_sample_todo(bob(), "Ok");
+    assert_eq!(
+        env.update::<TodoResult>(bob(), "restore_todo", encode_one(first.id).unwrap()),
+        over_quota
+    );
+}
+
+#[test]
+fn test_legacy_owner_adopts_the_anonymous_principals_data() {
+    let env = TestEnv::with_admins(vec![alice()]);
+    let config = Config {
+        allow_anonymous: true,
+        ..Config::default()
+    };
+    let set: ConfigResult = env.update(alice(), "set_config", encode_one(config).unwrap());
+    assert!(matches!(set, ConfigResult::Ok(_)));
+    let anonymous = Principal::anonymous();
+    let list = env.create_list(anonymous, "Errands");
+    let input = CreateTodoInput {
+        text: "Post letter".to_string(),
+        list_id: Some(list.id),
+        ..Default::default()
+    };
+    let filed: TodoResult = env.update(anonymous, "add_todo", encode_one(input).unwrap());
+    assert!(matches!(filed, TodoResult::Ok(_)));
+    let loose = env.create_sample_todo(anonymous, "Water plants");
+    let newer = env.create_sample_todo(anonymous, "Feed cat");
+    let own = env.create_sample_todo(bob(), "Call plumber");
+
+    env.upgrade_with(
+        encode_one(Some(InitArgs {
+            legacy_owner: Some(bob()),
+            ..Default::default()
+        }))
+        .unwrap(),
+    );
+
+    assert_eq!(env.get_todo_count(bob()), 4);
+    assert_eq!(env.get_todo_count(anonymous), 0);
+    match env.get_todo(bob(), loose.id) {
+        TodoResult::Ok(todo) => assert_eq!(todo.owner, bob()),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    // Adopted todos go after the owner's own, in the order they had.
+    assert_eq!(
+        env.ids_in_user_order(bob(), None),
+        vec![own.id, newer.id, loose.id]
+    );
+    let lists = env.get_lists(bob(), false);
+    assert_eq!(lists.len(), 1);
+    assert_eq!((lists[0].id, lists[0].owner), (list.id, bob()));
+}
+
+/// `Todo` as the first release returns it.
+#[derive(CandidType, Deserialize)]
+struct BaselineTodo {
+    id: u64,
+    text: String,
+}
+
+#[derive(CandidType, Deserialize)]
+enum BaselineTodoResult {
+    Ok(BaselineTodo),
+    Err(String),
+}
+
+#[test]
+fn test_upgrade_from_the_first_release_migrates_its_json_todos() {
+    let env = TestEnv::install_wasm(baseline_wasm(), encode_args(()).unwrap());
+    for text in ["Buy milk", "Walk dog", "Pay rent"] {
+        let input = CreateTodoInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        match env.update(alice(), "add_todo", encode_one(input).unwrap()) {
+            BaselineTodoResult::Ok(todo) => assert_eq!(todo.text, text),
+            BaselineTodoResult::Err(e) => panic!("Expected success, got error: {}", e),
         }
     }
+    match env.update(
+        alice(),
+        "update_todo_completed",
+        encode_args((2u64, true)).unwrap(),
+    ) {
+        BaselineTodoResult::Ok(todo) => assert_eq!(todo.id, 2),
+        BaselineTodoResult::Err(e) => panic!("Expected success, got error: {}", e),
+    }
 
-    #[test]
-    fn test_get_todo_count() {
-        let mut service = create_test_service();
-        assert_eq!(service.get_todo_count(), 0);
+    // Room for the three texts plus one four-letter todo.
+    let config = Config {
+        max_bytes_per_owner: 3 * 8 + 4,
+        ..Config::default()
+    };
+    env.upgrade_with(
+        encode_one(Some(InitArgs {
+            config: Some(config),
+            legacy_owner: Some(alice()),
+            ..Default::default()
+        }))
+        .unwrap(),
+    );
+
+    // v1 re-encodes the records and the legacy owner adopts them.
+    match env.get_todo(alice(), 2) {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.text, "Walk dog");
+            assert!(todo.completed);
+            assert_eq!(todo.owner, alice());
+        }
+        TodoResult::Err(e) => panic!("Expected the migrated todo: {:?}", e),
+    }
+    // v2 indexes them by creation time and per owner.
+    assert_eq!(env.get_todo_count(alice()), 3);
+    match env.get_all_todos(alice(), 0, 10) {
+        TodosResult::Ok(page) => {
+            let ids: Vec<u64> = page.todos.iter().map(|t| t.id).collect();
+            assert_eq!(ids, vec![3, 2, 1]);
+        }
+        TodosResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    // v3 indexes their words.
+    assert_eq!(env.search_texts(alice(), "milk"), vec!["Buy milk"]);
+    // v4 ranks them.
+    assert_eq!(env.ids_in_user_order(alice(), None).len(), 3);
+    // v5 counts their bytes, and the id counter carries on.
+    match env.add_todo(alice(), "Dust") {
+        TodoResult::Ok(todo) => assert_eq!(todo.id, 4),
+        TodoResult::Err(e) => panic!("Expected success, got error: {:?}", e),
+    }
+    assert_eq!(
+        env.add_todo(alice(), "Mop"),
+        TodoResult::Err(TodoError::QuotaExceeded {
+            quota: "max_bytes_per_owner".to_string(),
+            limit: 3 * 8 + 4,
+        })
+    );
+}
 
-        create_sample_todo(&mut service, "First");
-        assert_eq!(service.get_todo_count(), 1);
+#[test]
+fn test_todos_survive_upgrade() {
+    let env = TestEnv::new();
+    let first = env.create_sample_todo(alice(), "Before upgrade");
+    env.update_todo_completed(alice(), first.id, true);
+    env.create_sample_todo(bob(), "Bob before upgrade");
 
-        create_sample_todo(&mut service, "Second");
-        assert_eq!(service.get_todo_count(), 2);
+    env.upgrade();
 
-        service.delete_todo(1);
-        assert_eq!(service.get_todo_count(), 1);
+    match env.get_todo(alice(), first.id) {
+        TodoResult::Ok(todo) => {
+            assert_eq!(todo.text, "Before upgrade");
+            assert!(todo.completed);
+            assert_eq!(todo.owner, alice());
+        }
+        TodoResult::Err(e) => panic!("Todo should survive upgrade: {:?}", e),
     }
+    assert_eq!(env.get_todo_count(alice()), 1);
+    assert_eq!(env.get_todo_count(bob()), 1);
+
+    // The id counter must continue where it left off.
+    let after = env.create_sample_todo(alice(), "After upgrade");
+    assert_eq!(after.id, 3);
 }
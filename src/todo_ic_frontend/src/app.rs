@@ -1,7 +1,8 @@
 use leptos::*;
 use leptos_meta::*;
-use crate::components::{TodoForm, TodoList, NetworkSelector};
-use crate::types::Network;
+use crate::backend::provide_backend;
+use crate::components::{TodoForm, TodoList, NetworkSelector, ReminderNotifications};
+use crate::types::{ListId, Network};
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -9,6 +10,9 @@ pub fn App() -> impl IntoView {
 
     let (current_network, set_current_network) = create_signal(Network::Local);
     let (refresh_trigger, set_refresh_trigger) = create_signal(false);
+    // `None` shows every todo; new todos go into the selected list.
+    let (selected_list, set_selected_list) = create_signal(None::<ListId>);
+    provide_backend(current_network);
 
     view! {
         <Stylesheet id="leptos" href="/pkg/todo_ic_frontend.css"/>
@@ -34,14 +38,14 @@ pub fn App() -> impl IntoView {
                         on_network_change=set_current_network
                     />
 
-                    <TodoForm
-                        network=current_network
-                        on_todo_added=set_refresh_trigger
-                    />
+                    <ReminderNotifications/>
+
+                    <TodoForm on_todo_added=set_refresh_trigger list_id=selected_list/>
 
                     <TodoList
-                        network=current_network
                         refresh_trigger=refresh_trigger
+                        selected_list=selected_list
+                        on_list_change=set_selected_list
                     />
                 </div>
             </main>
@@ -0,0 +1,83 @@
+use super::TodoBackend;
+use crate::ic_client::IcClient;
+use crate::types::*;
+use async_trait::async_trait;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Backend that forwards every call to `todo_ic_backend` on `network`.
+/// The agent is created on first use and reused afterwards.
+pub struct CanisterBackend {
+    network: Network,
+    client: RefCell<Option<Rc<IcClient>>>,
+}
+
+impl CanisterBackend {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            client: RefCell::new(None),
+        }
+    }
+
+    async fn client(&self) -> Result<Rc<IcClient>, ClientError> {
+        if let Some(client) = self.client.borrow().clone() {
+            return Ok(client);
+        }
+        let client = Rc::new(IcClient::new(self.network.clone()).await?);
+        *self.client.borrow_mut() = Some(client.clone());
+        Ok(client)
+    }
+}
+
+#[async_trait(?Send)]
+impl TodoBackend for CanisterBackend {
+    async fn add_todo(&self, text: String, list_id: Option<ListId>) -> Result<Todo, ClientError> {
+        self.client().await?.add_todo(text, list_id).await
+    }
+
+    async fn get_all_todos(&self, list_id: Option<ListId>, offset: u64, limit: u64) -> Result<TodosPage, ClientError> {
+        self.client().await?.get_all_todos(list_id, offset, limit).await
+    }
+
+    async fn update_todo(&self, input: UpdateTodoInput) -> Result<Todo, ClientError> {
+        self.client().await?.update_todo(input).await
+    }
+
+    async fn move_todo(&self, id: u64, before: Option<u64>, after: Option<u64>) -> Result<Todo, ClientError> {
+        self.client().await?.move_todo(id, before, after).await
+    }
+
+    async fn delete_todo(&self, id: u64) -> Result<bool, ClientError> {
+        self.client().await?.delete_todo(id).await
+    }
+
+    async fn get_todo_count(&self) -> Result<u64, ClientError> {
+        self.client().await?.get_todo_count().await
+    }
+
+    async fn get_lists(&self) -> Result<Vec<TodoList>, ClientError> {
+        let client = self.client().await?;
+        let mut lists = client.get_lists().await?;
+        let shared = client.list_shared_with_me().await?;
+        lists.extend(
+            shared
+                .into_iter()
+                .map(|shared| shared.list)
+                .filter(|list| !list.archived),
+        );
+        Ok(lists)
+    }
+
+    async fn create_list(&self, name: String) -> Result<TodoList, ClientError> {
+        self.client().await?.create_list(name).await
+    }
+
+    async fn get_notifications(&self) -> Result<Vec<Notification>, ClientError> {
+        self.client().await?.get_notifications().await
+    }
+
+    async fn ack_notifications(&self, ids: Vec<NotificationId>) -> Result<u64, ClientError> {
+        self.client().await?.ack_notifications(ids).await
+    }
+}
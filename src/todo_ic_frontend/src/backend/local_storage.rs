@@ -0,0 +1,179 @@
+use super::{
+    move_todo_in, new_list, new_todo, paginate, top_rank, update_todo_in, validate_list_name,
+    validate_list_reference, validate_todo_text, TodoBackend,
+};
+use crate::types::*;
+use async_trait::async_trait;
+use web_sys::{window, Storage};
+
+/// Offline demo backend that keeps todos as JSON in the browser's
+/// localStorage under keys prefixed with `namespace`.
+pub struct LocalStorageBackend {
+    namespace: String,
+}
+
+impl LocalStorageBackend {
+    pub fn new(namespace: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+        }
+    }
+
+    fn storage(&self) -> Result<Storage, ClientError> {
+        let window = window().ok_or_else(|| ClientError::Transport("No window object".to_string()))?;
+        window.local_storage()
+            .map_err(|_| ClientError::Transport("Failed to get local storage".to_string()))?
+            .ok_or_else(|| ClientError::Transport("Local storage not available".to_string()))
+    }
+
+    fn get_todos_key(&self) -> String {
+        format!("todos_{}", self.namespace)
+    }
+
+    fn get_lists_key(&self) -> String {
+        format!("lists_{}", self.namespace)
+    }
+
+    fn get_next_id(&self, storage: &Storage) -> u64 {
+        self.next_counter_value(storage, &format!("next_id_{}", self.namespace))
+    }
+
+    fn get_next_list_id(&self, storage: &Storage) -> u64 {
+        self.next_counter_value(storage, &format!("next_list_id_{}", self.namespace))
+    }
+
+    fn next_counter_value(&self, storage: &Storage, key: &str) -> u64 {
+        let current_id = storage.get_item(key)
+            .unwrap_or(None)
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(1);
+
+        let next_id = current_id + 1;
+        let _ = storage.set_item(key, &next_id.to_string());
+        current_id
+    }
+
+    fn load_todos(&self, storage: &Storage) -> Vec<Todo> {
+        storage.get_item(&self.get_todos_key())
+            .unwrap_or(None)
+            .and_then(|json_str| serde_json::from_str(&json_str).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_todos(&self, storage: &Storage, todos: &[Todo]) -> Result<(), ClientError> {
+        let json_str = serde_json::to_string(todos)
+            .map_err(|e| ClientError::Transport(format!("Failed to serialize todos: {:?}", e)))?;
+
+        storage.set_item(&self.get_todos_key(), &json_str)
+            .map_err(|_| ClientError::Transport("Failed to save to local storage".to_string()))
+    }
+
+    fn load_lists(&self, storage: &Storage) -> Vec<TodoList> {
+        storage.get_item(&self.get_lists_key())
+            .unwrap_or(None)
+            .and_then(|json_str| serde_json::from_str(&json_str).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_lists(&self, storage: &Storage, lists: &[TodoList]) -> Result<(), ClientError> {
+        let json_str = serde_json::to_string(lists)
+            .map_err(|e| ClientError::Transport(format!("Failed to serialize lists: {:?}", e)))?;
+
+        storage.set_item(&self.get_lists_key(), &json_str)
+            .map_err(|_| ClientError::Transport("Failed to save to local storage".to_string()))
+    }
+
+    fn now() -> u64 {
+        // Match the canister's nanosecond timestamps.
+        (js_sys::Date::now() * 1_000_000.0) as u64
+    }
+}
+
+#[async_trait(?Send)]
+impl TodoBackend for LocalStorageBackend {
+    async fn add_todo(&self, text: String, list_id: Option<ListId>) -> Result<Todo, ClientError> {
+        validate_todo_text(&text)?;
+        let storage = self.storage()?;
+        validate_list_reference(&self.load_lists(&storage), list_id)?;
+        let mut todos = self.load_todos(&storage);
+
+        let rank = top_rank(&todos, list_id);
+        let todo = new_todo(self.get_next_id(&storage), &text, list_id, rank, Self::now());
+        todos.push(todo.clone());
+        self.save_todos(&storage, &todos)?;
+
+        Ok(todo)
+    }
+
+    async fn get_all_todos(&self, list_id: Option<ListId>, offset: u64, limit: u64) -> Result<TodosPage, ClientError> {
+        let storage = self.storage()?;
+        Ok(paginate(self.load_todos(&storage), list_id, offset, limit))
+    }
+
+    async fn update_todo(&self, input: UpdateTodoInput) -> Result<Todo, ClientError> {
+        let storage = self.storage()?;
+        let mut todos = self.load_todos(&storage);
+
+        let todo = update_todo_in(&mut todos, input, Self::now())?;
+        self.save_todos(&storage, &todos)?;
+
+        Ok(todo)
+    }
+
+    async fn move_todo(&self, id: u64, before: Option<u64>, after: Option<u64>) -> Result<Todo, ClientError> {
+        let storage = self.storage()?;
+        let mut todos = self.load_todos(&storage);
+
+        let todo = move_todo_in(&mut todos, id, before, after)?;
+        self.save_todos(&storage, &todos)?;
+
+        Ok(todo)
+    }
+
+    async fn delete_todo(&self, id: u64) -> Result<bool, ClientError> {
+        let storage = self.storage()?;
+        let mut todos = self.load_todos(&storage);
+        let initial_len = todos.len();
+
+        todos.retain(|t| t.id != id);
+
+        if todos.len() == initial_len {
+            return Err(TodoError::NotFound { id }.into());
+        }
+        self.save_todos(&storage, &todos)?;
+        Ok(true)
+    }
+
+    async fn get_todo_count(&self) -> Result<u64, ClientError> {
+        let storage = self.storage()?;
+        Ok(self.load_todos(&storage).len() as u64)
+    }
+
+    async fn get_lists(&self) -> Result<Vec<TodoList>, ClientError> {
+        let storage = self.storage()?;
+        let mut lists = self.load_lists(&storage);
+        lists.retain(|list| !list.archived);
+        Ok(lists)
+    }
+
+    async fn create_list(&self, name: String) -> Result<TodoList, ClientError> {
+        validate_list_name(&name)?;
+        let storage = self.storage()?;
+        let mut lists = self.load_lists(&storage);
+
+        let list = new_list(self.get_next_list_id(&storage), &name, Self::now());
+        lists.push(list.clone());
+        self.save_lists(&storage, &lists)?;
+
+        Ok(list)
+    }
+
+    // Nothing delivers reminders offline, so the inbox is always empty.
+    async fn get_notifications(&self) -> Result<Vec<Notification>, ClientError> {
+        Ok(Vec::new())
+    }
+
+    async fn ack_notifications(&self, _ids: Vec<NotificationId>) -> Result<u64, ClientError> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,194 @@
+use super::{
+    move_todo_in, new_list, new_todo, paginate, top_rank, update_todo_in, validate_list_name,
+    validate_list_reference, validate_todo_text, TodoBackend,
+};
+use crate::types::*;
+use async_trait::async_trait;
+use std::cell::{Cell, RefCell};
+
+/// Heap-only backend for component tests. Timestamps come from a logical
+/// clock that ticks once per mutation, so ordering is deterministic.
+#[derive(Default)]
+pub struct InMemoryBackend {
+    todos: RefCell<Vec<Todo>>,
+    lists: RefCell<Vec<TodoList>>,
+    clock: Cell<u64>,
+}
+
+impl InMemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.set(self.clock.get() + 1);
+        self.clock.get()
+    }
+}
+
+#[async_trait(?Send)]
+impl TodoBackend for InMemoryBackend {
+    async fn add_todo(&self, text: String, list_id: Option<ListId>) -> Result<Todo, ClientError> {
+        validate_todo_text(&text)?;
+        validate_list_reference(&self.lists.borrow(), list_id)?;
+        let now = self.tick();
+        let mut todos = self.todos.borrow_mut();
+        let id = todos.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+        let rank = top_rank(&todos, list_id);
+        let todo = new_todo(id, &text, list_id, rank, now);
+        todos.push(todo.clone());
+        Ok(todo)
+    }
+
+    async fn get_all_todos(&self, list_id: Option<ListId>, offset: u64, limit: u64) -> Result<TodosPage, ClientError> {
+        Ok(paginate(self.todos.borrow().clone(), list_id, offset, limit))
+    }
+
+    async fn update_todo(&self, input: UpdateTodoInput) -> Result<Todo, ClientError> {
+        let now = self.tick();
+        update_todo_in(&mut self.todos.borrow_mut(), input, now)
+    }
+
+    async fn move_todo(&self, id: u64, before: Option<u64>, after: Option<u64>) -> Result<Todo, ClientError> {
+        move_todo_in(&mut self.todos.borrow_mut(), id, before, after)
+    }
+
+    async fn delete_todo(&self, id: u64) -> Result<bool, ClientError> {
+        let mut todos = self.todos.borrow_mut();
+        let initial_len = todos.len();
+        todos.retain(|t| t.id != id);
+        if todos.len() == initial_len {
+            return Err(TodoError::NotFound { id }.into());
+        }
+        Ok(true)
+    }
+
+    async fn get_todo_count(&self) -> Result<u64, ClientError> {
+        Ok(self.todos.borrow().len() as u64)
+    }
+
+    async fn get_lists(&self) -> Result<Vec<TodoList>, ClientError> {
+        Ok(self.lists.borrow().iter().filter(|list| !list.archived).cloned().collect())
+    }
+
+    async fn create_list(&self, name: String) -> Result<TodoList, ClientError> {
+        validate_list_name(&name)?;
+        let now = self.tick();
+        let mut lists = self.lists.borrow_mut();
+        let id = lists.iter().map(|l| l.id).max().unwrap_or(0) + 1;
+        let list = new_list(id, &name, now);
+        lists.push(list.clone());
+        Ok(list)
+    }
+
+    // Nothing delivers reminders offline, so the inbox is always empty.
+    async fn get_notifications(&self) -> Result<Vec<Notification>, ClientError> {
+        Ok(Vec::new())
+    }
+
+    async fn ack_notifications(&self, _ids: Vec<NotificationId>) -> Result<u64, ClientError> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn lists_newest_first_with_pagination() {
+        let backend = InMemoryBackend::new();
+        for text in ["First", "Second", "Third"] {
+            block_on(backend.add_todo(text.to_string(), None)).unwrap();
+        }
+
+        let page = block_on(backend.get_all_todos(None, 0, 2)).unwrap();
+        let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["Third", "Second"]);
+        assert_eq!(page.total_count, 3);
+        assert!(page.has_more);
+    }
+
+    #[test]
+    fn rejects_empty_text_and_unknown_ids() {
+        let backend = InMemoryBackend::new();
+
+        assert!(matches!(
+            block_on(backend.add_todo("   ".to_string(), None)),
+            Err(ClientError::Api(TodoError::Validation { .. }))
+        ));
+        assert_eq!(
+            block_on(backend.delete_todo(42)),
+            Err(ClientError::Api(TodoError::NotFound { id: 42 }))
+        );
+    }
+
+    #[test]
+    fn rejects_updates_based_on_a_stale_read() {
+        let backend = InMemoryBackend::new();
+        let todo = block_on(backend.add_todo("Shared".to_string(), None)).unwrap();
+        let toggle = |expected_version| UpdateTodoInput {
+            id: todo.id,
+            text: None,
+            completed: Some(true),
+            expected_version: Some(expected_version),
+            ..Default::default()
+        };
+
+        let current = block_on(backend.update_todo(toggle(todo.version))).unwrap();
+        assert_eq!(
+            block_on(backend.update_todo(toggle(todo.version))),
+            Err(ClientError::Api(TodoError::Conflict {
+                id: todo.id,
+                version: current.version,
+            }))
+        );
+    }
+
+    #[test]
+    fn updates_that_only_set_a_reminder_are_kept() {
+        let backend = InMemoryBackend::new();
+        let todo = block_on(backend.add_todo("Call mum".to_string(), None)).unwrap();
+        let input = UpdateTodoInput {
+            id: todo.id,
+            remind_at: Some(Some(42)),
+            ..Default::default()
+        };
+
+        let updated = block_on(backend.update_todo(input)).unwrap();
+        assert_eq!(updated.remind_at, Some(42));
+    }
+
+    #[test]
+    fn moves_todos_between_neighbours() {
+        let backend = InMemoryBackend::new();
+        let ids: Vec<u64> = ["First", "Second", "Third"]
+            .iter()
+            .map(|text| block_on(backend.add_todo(text.to_string(), None)).unwrap().id)
+            .collect();
+
+        // Listed as Third, Second, First; move First between the other two.
+        block_on(backend.move_todo(ids[0], Some(ids[2]), Some(ids[1]))).unwrap();
+        let page = block_on(backend.get_all_todos(None, 0, 10)).unwrap();
+        let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["Third", "First", "Second"]);
+    }
+
+    #[test]
+    fn filters_todos_by_list() {
+        let backend = InMemoryBackend::new();
+        let work = block_on(backend.create_list("Work".to_string())).unwrap();
+        block_on(backend.add_todo("Report".to_string(), Some(work.id))).unwrap();
+        block_on(backend.add_todo("Laundry".to_string(), None)).unwrap();
+
+        let page = block_on(backend.get_all_todos(Some(work.id), 0, 10)).unwrap();
+        let texts: Vec<&str> = page.todos.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["Report"]);
+        assert_eq!(block_on(backend.get_all_todos(None, 0, 10)).unwrap().total_count, 2);
+        assert!(matches!(
+            block_on(backend.add_todo("Lost".to_string(), Some(99))),
+            Err(ClientError::Api(TodoError::Validation { .. }))
+        ));
+    }
+}
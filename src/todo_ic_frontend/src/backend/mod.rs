@@ -0,0 +1,264 @@
+pub mod canister;
+pub mod local_storage;
+pub mod memory;
+
+pub use canister::CanisterBackend;
+pub use local_storage::LocalStorageBackend;
+pub use memory::InMemoryBackend;
+
+use crate::types::rank::rank_between;
+use crate::types::*;
+use async_trait::async_trait;
+use candid::Principal;
+use leptos::*;
+use std::rc::Rc;
+
+/// Everything the UI needs from a todo store. Components only ever see this
+/// trait, so the canister, the offline demo store and the in-memory test
+/// store are interchangeable.
+#[async_trait(?Send)]
+pub trait TodoBackend {
+    async fn add_todo(&self, text: String, list_id: Option<ListId>) -> Result<Todo, ClientError>;
+    /// Todos in the user's manual order. `list_id` restricts the page to one
+    /// list; `None` lists every todo.
+    async fn get_all_todos(&self, list_id: Option<ListId>, offset: u64, limit: u64) -> Result<TodosPage, ClientError>;
+    async fn update_todo(&self, input: UpdateTodoInput) -> Result<Todo, ClientError>;
+    /// Places `id` between the todos `before` and `after` of its list.
+    async fn move_todo(&self, id: u64, before: Option<u64>, after: Option<u64>) -> Result<Todo, ClientError>;
+    async fn delete_todo(&self, id: u64) -> Result<bool, ClientError>;
+    async fn get_todo_count(&self) -> Result<u64, ClientError>;
+    /// The caller's unarchived lists, oldest first, followed by unarchived
+    /// lists others have shared with them.
+    async fn get_lists(&self) -> Result<Vec<TodoList>, ClientError>;
+    async fn create_list(&self, name: String) -> Result<TodoList, ClientError>;
+    /// Reminders that have come due and were not yet acknowledged, oldest
+    /// first.
+    async fn get_notifications(&self) -> Result<Vec<Notification>, ClientError>;
+    /// Removes the given notifications from the inbox; returns how many were
+    /// removed.
+    async fn ack_notifications(&self, ids: Vec<NotificationId>) -> Result<u64, ClientError>;
+}
+
+pub type SharedBackend = Rc<dyn TodoBackend>;
+
+pub fn backend_for(network: &Network) -> SharedBackend {
+    match network {
+        Network::Demo => Rc::new(LocalStorageBackend::new("demo")),
+        network => Rc::new(CanisterBackend::new(network.clone())),
+    }
+}
+
+/// Makes the backend for the selected network available to every component.
+/// The backend is rebuilt whenever `network` changes.
+pub fn provide_backend(network: ReadSignal<Network>) {
+    let (backend, set_backend) = create_signal(backend_for(&network.get_untracked()));
+    create_effect(move |previous: Option<()>| {
+        let network = network.get();
+        // The signal already holds the initial backend; only rebuild on changes.
+        if previous.is_some() {
+            set_backend.set(backend_for(&network));
+        }
+    });
+    provide_context(backend);
+}
+
+pub fn use_backend() -> ReadSignal<SharedBackend> {
+    expect_context::<ReadSignal<SharedBackend>>()
+}
+
+/// Mirrors `validate_todo_text` in the canister so offline backends reject
+/// the same input.
+fn validate_todo_text(text: &str) -> Result<(), TodoError> {
+    if text.trim().is_empty() {
+        return Err(TodoError::validation("text", "cannot be empty"));
+    }
+    if text.len() > MAX_TODO_TEXT_LENGTH {
+        return Err(TodoError::validation(
+            "text",
+            format!("cannot exceed {} characters", MAX_TODO_TEXT_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// Mirrors `validate_list_name` in the canister.
+fn validate_list_name(name: &str) -> Result<(), TodoError> {
+    if name.trim().is_empty() {
+        return Err(TodoError::validation("name", "cannot be empty"));
+    }
+    if name.trim().len() > MAX_LIST_NAME_LENGTH {
+        return Err(TodoError::validation(
+            "name",
+            format!("cannot exceed {} characters", MAX_LIST_NAME_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// Mirrors `validate_list_reference` in the canister for a single-user store.
+fn validate_list_reference(lists: &[TodoList], list_id: Option<ListId>) -> Result<(), TodoError> {
+    match list_id.map(|id| lists.iter().find(|list| list.id == id)) {
+        None => Ok(()),
+        Some(Some(list)) if !list.archived => Ok(()),
+        Some(Some(_)) => Err(TodoError::validation("list_id", "is archived")),
+        Some(None) => Err(TodoError::validation("list_id", "does not refer to one of your lists")),
+    }
+}
+
+fn new_list(id: ListId, name: &str, now: u64) -> TodoList {
+    TodoList {
+        id,
+        owner: Principal::anonymous(),
+        name: name.trim().to_string(),
+        colour: DEFAULT_LIST_COLOUR.to_string(),
+        archived: false,
+        created_at: now,
+    }
+}
+
+/// Rank that puts a new todo at the top of `list_id`, as the canister does.
+fn top_rank(todos: &[Todo], list_id: Option<ListId>) -> String {
+    let first = todos
+        .iter()
+        .filter(|t| t.list_id == list_id)
+        .map(|t| t.rank.as_str())
+        .filter(|rank| !rank.is_empty())
+        .min();
+    rank_between(None, first)
+}
+
+fn new_todo(id: u64, text: &str, list_id: Option<ListId>, rank: String, now: u64) -> Todo {
+    Todo {
+        id,
+        owner: Principal::anonymous(),
+        text: text.trim().to_string(),
+        completed: false,
+        created_at: now,
+        updated_at: now,
+        priority: None,
+        due_at: None,
+        tags: Vec::new(),
+        list_id,
+        parent_id: None,
+        rank,
+        recurrence: None,
+        remind_at: None,
+        version: 0,
+    }
+}
+
+/// Mirrors `service::update_todo` in the canister, including the
+/// `expected_version` conflict check, and returns the updated copy.
+fn update_todo_in(todos: &mut [Todo], input: UpdateTodoInput, now: u64) -> Result<Todo, ClientError> {
+    if let Some(text) = &input.text {
+        validate_todo_text(text)?;
+    }
+    let todo = todos
+        .iter_mut()
+        .find(|t| t.id == input.id)
+        .ok_or(TodoError::NotFound { id: input.id })?;
+    if let Some(expected) = input.expected_version {
+        if expected != todo.version {
+            return Err(TodoError::Conflict {
+                id: todo.id,
+                version: todo.version,
+            }
+            .into());
+        }
+    }
+
+    let changes_nothing = input.text.is_none()
+        && input.completed.is_none()
+        && input.priority.is_none()
+        && input.due_at.is_none()
+        && input.tags.is_none()
+        && input.list_id.is_none()
+        && input.recurrence.is_none()
+        && input.remind_at.is_none();
+    if changes_nothing {
+        return Ok(todo.clone());
+    }
+    if let Some(text) = input.text {
+        todo.text = text.trim().to_string();
+    }
+    if let Some(completed) = input.completed {
+        todo.completed = completed;
+    }
+    if let Some(priority) = input.priority {
+        todo.priority = priority;
+    }
+    if let Some(due_at) = input.due_at {
+        todo.due_at = due_at;
+    }
+    if let Some(tags) = input.tags {
+        let mut tags: Vec<String> = tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+        tags.sort();
+        tags.dedup();
+        todo.tags = tags;
+    }
+    if let Some(list_id) = input.list_id {
+        todo.list_id = list_id;
+    }
+    if let Some(recurrence) = input.recurrence {
+        todo.recurrence = recurrence;
+    }
+    if let Some(remind_at) = input.remind_at {
+        todo.remind_at = remind_at;
+    }
+    todo.updated_at = now;
+    todo.version += 1;
+    Ok(todo.clone())
+}
+
+/// Mirrors `service::move_todo` in the canister and returns the moved copy.
+fn move_todo_in(todos: &mut [Todo], id: u64, before: Option<u64>, after: Option<u64>) -> Result<Todo, ClientError> {
+    let moved = todos
+        .iter()
+        .find(|t| t.id == id)
+        .ok_or(TodoError::NotFound { id })?
+        .clone();
+    if before.is_none() && after.is_none() {
+        return Err(TodoError::validation("before", "either before or after is required").into());
+    }
+    let neighbour_rank = |field: &str, neighbour: Option<u64>| -> Result<Option<String>, TodoError> {
+        match neighbour {
+            None => Ok(None),
+            Some(neighbour) => todos
+                .iter()
+                .find(|t| t.id == neighbour && t.id != id && t.list_id == moved.list_id)
+                .map(|t| Some(t.rank.clone()))
+                .ok_or_else(|| TodoError::validation(field, "must be another todo in the same list")),
+        }
+    };
+    let low = neighbour_rank("before", before)?;
+    let high = neighbour_rank("after", after)?;
+    if let (Some(low), Some(high)) = (&low, &high) {
+        if low >= high {
+            return Err(TodoError::validation("after", "must come later in the list than before").into());
+        }
+    }
+
+    let rank = rank_between(low.as_deref(), high.as_deref());
+    let todo = todos.iter_mut().find(|t| t.id == id).expect("moved todo was found above");
+    todo.rank = rank;
+    Ok(todo.clone())
+}
+
+/// Page over `todos` in `list_id` in the user's manual order, matching the
+/// canister's `Rank` sort. Offline backends page by offset only and never
+/// hand out cursors.
+fn paginate(mut todos: Vec<Todo>, list_id: Option<ListId>, offset: u64, limit: u64) -> TodosPage {
+    todos.retain(|t| list_id.is_none_or(|id| t.list_id == Some(id)));
+    todos.sort_by(|a, b| (a.list_id, &a.rank, a.id).cmp(&(b.list_id, &b.rank, b.id)));
+
+    let total_count = todos.len() as u64;
+    let start = std::cmp::min(offset as usize, todos.len());
+    let end = std::cmp::min(start + limit as usize, todos.len());
+
+    TodosPage {
+        todos: todos[start..end].to_vec(),
+        total_count,
+        has_more: end < todos.len(),
+        next_cursor: None,
+    }
+}
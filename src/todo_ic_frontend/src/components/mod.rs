@@ -1,7 +1,9 @@
 pub mod todo_form;
 pub mod todo_list;
 pub mod network_selector;
+pub mod reminders;
 
 pub use todo_form::TodoForm;
 pub use todo_list::TodoList;
-pub use network_selector::NetworkSelector;
\ No newline at end of file
+pub use network_selector::NetworkSelector;
+pub use reminders::ReminderNotifications;
\ No newline at end of file
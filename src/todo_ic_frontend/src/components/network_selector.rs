@@ -40,6 +40,16 @@ pub fn NetworkSelector(
                 >
                     "🌍 Mainnet"
                 </button>
+                <button
+                    class=move || if current_network.get() == Network::Demo {
+                        "network-btn active demo"
+                    } else {
+                        "network-btn demo"
+                    }
+                    on:click=move |_| on_network_change.set(Network::Demo)
+                >
+                    "📴 Demo (offline)"
+                </button>
             </div>
             <div class="network-info">
                 <p>
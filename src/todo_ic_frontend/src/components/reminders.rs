@@ -0,0 +1,84 @@
+use leptos::*;
+use crate::backend::use_backend;
+use crate::types::Notification as Reminder;
+use std::time::Duration;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Notification, NotificationOptions, NotificationPermission};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Polls the caller's notification inbox and raises a browser notification
+/// for every reminder that has come due. Reminders are only acknowledged once
+/// shown, so they wait in the inbox until the user allows notifications.
+#[component]
+pub fn ReminderNotifications() -> impl IntoView {
+    let backend = use_backend();
+    let (permission, set_permission) = create_signal(current_permission());
+
+    let poll = move || {
+        if permission.get_untracked() != Some(NotificationPermission::Granted) {
+            return;
+        }
+        let backend = backend.get_untracked();
+        spawn_local(async move {
+            // A failed poll is retried on the next tick.
+            let reminders = match backend.get_notifications().await {
+                Ok(reminders) => reminders,
+                Err(_) => return,
+            };
+            let shown: Vec<u64> = reminders
+                .iter()
+                .filter(|reminder| show_reminder(reminder))
+                .map(|reminder| reminder.id)
+                .collect();
+            if !shown.is_empty() {
+                let _ = backend.ack_notifications(shown).await;
+            }
+        });
+    };
+
+    poll();
+    if let Ok(handle) = set_interval_with_handle(poll, POLL_INTERVAL) {
+        on_cleanup(move || handle.clear());
+    }
+
+    let enable = move |_| {
+        let request = match Notification::request_permission() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+        spawn_local(async move {
+            let _ = JsFuture::from(request).await;
+            set_permission.set(current_permission());
+            poll();
+        });
+    };
+
+    view! {
+        <Show when=move || permission.get() == Some(NotificationPermission::Default)>
+            <div class="reminder-prompt">
+                <span>"🔔 Get a browser notification when a reminder is due"</span>
+                <button class="reminder-enable-btn" on:click=enable>
+                    "Enable reminders"
+                </button>
+            </div>
+        </Show>
+    }
+}
+
+/// The browser's notification permission, or `None` where the Notification
+/// API is unavailable.
+fn current_permission() -> Option<NotificationPermission> {
+    let supported = js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("Notification"))
+        .unwrap_or(false);
+    supported.then(Notification::permission)
+}
+
+fn show_reminder(reminder: &Reminder) -> bool {
+    let options = NotificationOptions::new();
+    options.set_body(&reminder.text);
+    // Replaces rather than stacks a notification the browser is still showing.
+    options.set_tag(&format!("todo-reminder-{}", reminder.id));
+    Notification::new_with_options("⏰ Todo reminder", &options).is_ok()
+}
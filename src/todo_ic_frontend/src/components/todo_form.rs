@@ -1,14 +1,15 @@
 use leptos::*;
 use leptos::html::Input;
-use crate::types::Network;
-use crate::ic_client::IcClient;
+use crate::backend::use_backend;
+use crate::types::{ClientError, ListId, TodoError};
 use wasm_bindgen_futures::spawn_local;
 
 #[component]
 pub fn TodoForm(
-    network: ReadSignal<Network>,
     on_todo_added: WriteSignal<bool>,
+    list_id: ReadSignal<Option<ListId>>,
 ) -> impl IntoView {
+    let backend = use_backend();
     let (todo_text, set_todo_text) = create_signal(String::new());
     let (is_loading, set_is_loading) = create_signal(false);
     let (error_message, set_error_message) = create_signal(None::<String>);
@@ -25,26 +26,23 @@ pub fn TodoForm(
         set_is_loading.set(true);
         set_error_message.set(None);
 
-        let current_network = network.get();
+        let backend = backend.get();
+        let list_id = list_id.get_untracked();
 
         spawn_local(async move {
-            match IcClient::new(current_network).await {
-                Ok(client) => {
-                    match client.add_todo(text).await {
-                        Ok(_) => {
-                            set_todo_text.set(String::new());
-                            on_todo_added.update(|val| *val = !*val); // Trigger refresh
-                            if let Some(input) = input_ref.get() {
-                                let _ = input.focus();
-                            }
-                        }
-                        Err(err) => {
-                            set_error_message.set(Some(format!("Failed to add todo: {}", err)));
-                        }
+            match backend.add_todo(text, list_id).await {
+                Ok(_) => {
+                    set_todo_text.set(String::new());
+                    on_todo_added.update(|val| *val = !*val); // Trigger refresh
+                    if let Some(input) = input_ref.get() {
+                        let _ = input.focus();
                     }
                 }
+                Err(ClientError::Api(TodoError::Validation { reason, .. })) => {
+                    set_error_message.set(Some(format!("Todo text {}", reason)));
+                }
                 Err(err) => {
-                    set_error_message.set(Some(format!("Connection failed: {}", err)));
+                    set_error_message.set(Some(format!("Failed to add todo: {}", err)));
                 }
             }
             set_is_loading.set(false);
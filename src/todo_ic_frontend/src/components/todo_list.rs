@@ -1,14 +1,19 @@
 use leptos::*;
-use crate::types::{Todo, Network};
-use crate::ic_client::IcClient;
+use crate::backend::use_backend;
+use crate::types::{ClientError, ListId, Todo, TodoError, TodoList as ListInfo, UpdateTodoInput};
 use wasm_bindgen_futures::spawn_local;
 
 #[component]
 pub fn TodoList(
-    network: ReadSignal<Network>,
     refresh_trigger: ReadSignal<bool>,
+    selected_list: ReadSignal<Option<ListId>>,
+    on_list_change: WriteSignal<Option<ListId>>,
 ) -> impl IntoView {
+    let backend = use_backend();
     let (todos, set_todos) = create_signal(Vec::<Todo>::new());
+    let (lists, set_lists) = create_signal(Vec::<ListInfo>::new());
+    let (new_list_name, set_new_list_name) = create_signal(String::new());
+    let (dragged_todo, set_dragged_todo) = create_signal(None::<u64>);
     let (is_loading, set_is_loading) = create_signal(true);
     let (error_message, set_error_message) = create_signal(None::<String>);
     let (total_count, set_total_count) = create_signal(0u64);
@@ -17,80 +22,164 @@ pub fn TodoList(
         set_is_loading.set(true);
         set_error_message.set(None);
 
-        let current_network = network.get();
+        let backend = backend.get_untracked();
+        let list_id = selected_list.get_untracked();
 
         spawn_local(async move {
-            match IcClient::new(current_network).await {
-                Ok(client) => {
-                    match client.get_all_todos(0, 50).await {
-                        Ok(page) => {
-                            set_todos.set(page.todos);
-                            set_total_count.set(page.total_count);
-                        }
-                        Err(err) => {
-                            set_error_message.set(Some(format!("Failed to load todos: {}", err)));
-                        }
-                    }
+            match backend.get_all_todos(list_id, 0, 50).await {
+                Ok(page) => {
+                    set_todos.set(page.todos);
+                    set_total_count.set(page.total_count);
                 }
                 Err(err) => {
-                    set_error_message.set(Some(format!("Connection failed: {}", err)));
+                    set_error_message.set(Some(format!("Failed to load todos: {}", err)));
                 }
             }
             set_is_loading.set(false);
         });
     };
 
-    // Load todos when component mounts or network changes
+    let load_lists = move || {
+        let backend = backend.get_untracked();
+
+        spawn_local(async move {
+            match backend.get_lists().await {
+                Ok(loaded) => set_lists.set(loaded),
+                Err(err) => {
+                    set_error_message.set(Some(format!("Failed to load lists: {}", err)));
+                }
+            }
+        });
+    };
+
+    // Load lists when component mounts or the backend changes; list ids
+    // from the previous backend mean nothing to the new one.
     create_effect(move |_| {
-        network.track();
-        load_todos();
+        backend.track();
+        if selected_list.get_untracked().is_some() {
+            on_list_change.set(None);
+        }
+        load_lists();
     });
 
-    // Reload todos when refresh is triggered
+    // Reload todos when the backend, the selected list or refresh changes
     create_effect(move |_| {
+        backend.track();
+        selected_list.track();
         refresh_trigger.track();
         load_todos();
     });
 
+    let create_list = move |_| {
+        let name = new_list_name.get().trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        let backend = backend.get();
+
+        spawn_local(async move {
+            match backend.create_list(name).await {
+                Ok(list) => {
+                    set_new_list_name.set(String::new());
+                    let list_id = list.id;
+                    set_lists.update(|lists| lists.push(list));
+                    on_list_change.set(Some(list_id));
+                }
+                Err(err) => {
+                    set_error_message.set(Some(format!("Failed to create list: {}", err)));
+                }
+            }
+        });
+    };
+
+    // Dropping a todo on another one takes that todo's place: the dragged
+    // todo lands below it when dragged down and above it when dragged up.
+    // Ranks only order todos within one list, so in the "All" view a todo
+    // can only be dropped on, and placed between, todos of its own list.
+    let drop_on = move |target_id: u64| {
+        let dragged_id = match dragged_todo.get_untracked() {
+            Some(id) if id != target_id => id,
+            _ => return,
+        };
+        set_dragged_todo.set(None);
+
+        let todos = todos.get_untracked();
+        let Some(list_id) = todos.iter().find(|t| t.id == dragged_id).map(|t| t.list_id) else {
+            return;
+        };
+        let ids: Vec<u64> = todos.iter().filter(|t| t.list_id == list_id).map(|t| t.id).collect();
+        let (Some(from), Some(to)) = (
+            ids.iter().position(|&id| id == dragged_id),
+            ids.iter().position(|&id| id == target_id),
+        ) else {
+            return;
+        };
+        let (before, after) = if from < to {
+            (Some(target_id), ids.get(to + 1).copied())
+        } else {
+            (to.checked_sub(1).map(|i| ids[i]), Some(target_id))
+        };
+        let backend = backend.get();
+
+        spawn_local(async move {
+            match backend.move_todo(dragged_id, before, after).await {
+                Ok(_) => {
+                    load_todos();
+                }
+                Err(err) => {
+                    // The list changed under us; show the current order.
+                    load_todos();
+                    set_error_message.set(Some(format!("Failed to move todo: {}", err)));
+                }
+            }
+        });
+    };
+
+    let tab_class = move |list_id: Option<ListId>| {
+        if selected_list.get() == list_id { "list-tab active" } else { "list-tab" }
+    };
+
     let toggle_todo = move |todo: Todo| {
-        let current_network = network.get();
+        let backend = backend.get();
 
         spawn_local(async move {
-            match IcClient::new(current_network).await {
-                Ok(client) => {
-                    match client.update_todo_completed(todo.id, !todo.completed).await {
-                        Ok(_) => {
-                            load_todos();
-                        }
-                        Err(err) => {
-                            set_error_message.set(Some(format!("Failed to update todo: {}", err)));
-                        }
-                    }
+            let input = UpdateTodoInput {
+                id: todo.id,
+                text: None,
+                completed: Some(!todo.completed),
+                expected_version: Some(todo.version),
+                ..Default::default()
+            };
+            match backend.update_todo(input).await {
+                Ok(_) => {
+                    load_todos();
+                }
+                Err(err @ ClientError::Api(TodoError::NotFound { .. } | TodoError::Conflict { .. })) => {
+                    // Someone else removed or changed it; resync the list and keep the message.
+                    load_todos();
+                    set_error_message.set(Some(format!("Failed to update todo: {}", err)));
                 }
                 Err(err) => {
-                    set_error_message.set(Some(format!("Connection failed: {}", err)));
+                    set_error_message.set(Some(format!("Failed to update todo: {}", err)));
                 }
             }
         });
     };
 
     let delete_todo = move |todo_id: u64| {
-        let current_network = network.get();
+        let backend = backend.get();
 
         spawn_local(async move {
-            match IcClient::new(current_network).await {
-                Ok(client) => {
-                    match client.delete_todo(todo_id).await {
-                        Ok(_) => {
-                            load_todos();
-                        }
-                        Err(err) => {
-                            set_error_message.set(Some(format!("Failed to delete todo: {}", err)));
-                        }
-                    }
+            match backend.delete_todo(todo_id).await {
+                Ok(_) => {
+                    load_todos();
+                }
+                Err(ClientError::Api(TodoError::NotFound { .. })) => {
+                    // Already gone, which is what the user wanted.
+                    load_todos();
                 }
                 Err(err) => {
-                    set_error_message.set(Some(format!("Connection failed: {}", err)));
+                    set_error_message.set(Some(format!("Failed to delete todo: {}", err)));
                 }
             }
         });
@@ -113,6 +202,46 @@ pub fn TodoList(
                 </div>
             </div>
 
+            <div class="list-switcher">
+                <button
+                    class=move || tab_class(None)
+                    on:click=move |_| on_list_change.set(None)
+                >
+                    "All"
+                </button>
+                <For
+                    each=move || lists.get()
+                    key=|list| list.id
+                    children=move |list| {
+                        let list_id = list.id;
+
+                        view! {
+                            <button
+                                class=move || tab_class(Some(list_id))
+                                style=format!("border-color: {}", list.colour)
+                                on:click=move |_| on_list_change.set(Some(list_id))
+                            >
+                                {list.name.clone()}
+                            </button>
+                        }
+                    }
+                />
+                <input
+                    type="text"
+                    placeholder="New list"
+                    class="list-input"
+                    prop:value=move || new_list_name.get()
+                    on:input=move |ev| set_new_list_name.set(event_target_value(&ev))
+                />
+                <button
+                    class="add-list-btn"
+                    on:click=create_list
+                    prop:disabled=move || new_list_name.get().trim().is_empty()
+                >
+                    "➕ List"
+                </button>
+            </div>
+
             {move || error_message.get().map(|msg| view! {
                 <div class="error-message">
                     "❌ " {msg}
@@ -135,18 +264,38 @@ pub fn TodoList(
             } else {
                 view! {
                     <div class="todos">
+                        // Keyed by version too, so an edited todo is rendered
+                        // again instead of keeping the copy it was drawn from.
                         <For
                             each=move || todos.get()
-                            key=|todo| todo.id
+                            key=|todo| (todo.id, todo.version)
                             children=move |todo| {
                                 let todo_for_toggle = todo.clone();
                                 let todo_id = todo.id;
+                                let completed = todo.completed;
 
                                 view! {
-                                    <div class=format!(
-                                        "todo-item {}",
-                                        if todo.completed { "completed" } else { "" }
-                                    )>
+                                    <div
+                                        class=move || format!(
+                                            "todo-item {} {}",
+                                            if completed { "completed" } else { "" },
+                                            if dragged_todo.get() == Some(todo_id) { "dragging" } else { "" }
+                                        )
+                                        draggable="true"
+                                        on:dragstart=move |ev: web_sys::DragEvent| {
+                                            // Firefox only starts a drag that carries data.
+                                            if let Some(data) = ev.data_transfer() {
+                                                let _ = data.set_data("text/plain", &todo_id.to_string());
+                                            }
+                                            set_dragged_todo.set(Some(todo_id));
+                                        }
+                                        on:dragend=move |_| set_dragged_todo.set(None)
+                                        on:dragover=move |ev: web_sys::DragEvent| ev.prevent_default()
+                                        on:drop=move |ev: web_sys::DragEvent| {
+                                            ev.prevent_default();
+                                            drop_on(todo_id);
+                                        }
+                                    >
                                         <div class="todo-content">
                                             <button
                                                 class="toggle-btn"
@@ -1,132 +1,178 @@
+use crate::identity::browser_identity;
 use crate::types::*;
+use candid::{decode_one, encode_args, encode_one, CandidType, Principal};
+use ic_agent::Agent;
+use serde::de::DeserializeOwned;
 use web_sys::console;
-use leptos::*;
-use web_sys::{window, Storage};
-use serde_json;
 
+/// Talks to `todo_ic_backend` through the replica's HTTP interface.
+///
+/// Queries are answered directly; update calls are submitted and then polled
+/// via `read_state` until the certified reply is available. Envelope encoding,
+/// request ids and certificate verification are handled by `ic_agent`. Calls
+/// are signed with the browser's own identity, so each browser is a separate
+/// principal with its own todos, quotas and rate limit.
 pub struct IcClient {
-    network: Network,
-    storage: Storage,
+    agent: Agent,
+    canister_id: Principal,
 }
 
 impl IcClient {
-    pub async fn new(network: Network) -> Result<Self, String> {
-        console::log_1(&format!("Connecting to {} (using local storage mock)", network.display_name()).into());
+    pub async fn new(network: Network) -> Result<Self, ClientError> {
+        console::log_1(&format!("Connecting to {} at {}", network.display_name(), network.get_url()).into());
 
-        let window = window().ok_or("No window object")?;
-        let storage = window.local_storage()
-            .map_err(|_| "Failed to get local storage")?
-            .ok_or("Local storage not available")?;
+        let canister_id = Principal::from_text(network.get_canister_id())
+            .map_err(|e| ClientError::Transport(format!("Invalid canister id: {}", e)))?;
+
+        let agent = Agent::builder()
+            .with_url(network.get_url())
+            .with_identity(browser_identity()?)
+            .build()
+            .map_err(transport_error)?;
+
+        // A local replica signs with a freshly generated root key, so the agent
+        // has to fetch it before certificates can be verified. Never do this
+        // against mainnet, where the key is built into the agent.
+        if network == Network::Local {
+            agent.fetch_root_key().await.map_err(transport_error)?;
+        }
 
         Ok(Self {
-            network,
-            storage,
+            agent,
+            canister_id,
         })
     }
 
-    fn get_todos_key(&self) -> String {
-        format!("todos_{}", self.network.display_name().to_lowercase())
+    async fn query<R>(&self, method: &str, arg: Vec<u8>) -> Result<R, ClientError>
+    where
+        R: CandidType + DeserializeOwned,
+    {
+        let reply = self.agent
+            .query(&self.canister_id, method)
+            .with_arg(arg)
+            .call()
+            .await
+            .map_err(transport_error)?;
+        decode_reply(method, &reply)
     }
 
-    fn get_next_id(&self) -> u64 {
-        let key = format!("next_id_{}", self.network.display_name().to_lowercase());
-        let current_id = self.storage.get_item(&key)
-            .unwrap_or(None)
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(1);
-        
-        let next_id = current_id + 1;
-        let _ = self.storage.set_item(&key, &next_id.to_string());
-        current_id
+    async fn update<R>(&self, method: &str, arg: Vec<u8>) -> Result<R, ClientError>
+    where
+        R: CandidType + DeserializeOwned,
+    {
+        let reply = self.agent
+            .update(&self.canister_id, method)
+            .with_arg(arg)
+            .call_and_wait()
+            .await
+            .map_err(transport_error)?;
+        decode_reply(method, &reply)
     }
 
-    fn get_all_todos_from_storage(&self) -> Vec<Todo> {
-        let key = self.get_todos_key();
-        self.storage.get_item(&key)
-            .unwrap_or(None)
-            .and_then(|json_str| serde_json::from_str(&json_str).ok())
-            .unwrap_or_default()
+    pub async fn add_todo(&self, text: String, list_id: Option<ListId>) -> Result<Todo, ClientError> {
+        let input = CreateTodoInput {
+            text,
+            list_id,
+            ..Default::default()
+        };
+        let arg = encode_one(input).map_err(transport_error)?;
+        match self.update("add_todo", arg).await? {
+            TodoResult::Ok(todo) => Ok(todo),
+            TodoResult::Err(err) => Err(err.into()),
+        }
     }
 
-    fn save_todos_to_storage(&self, todos: &[Todo]) -> Result<(), String> {
-        let key = self.get_todos_key();
-        let json_str = serde_json::to_string(todos)
-            .map_err(|e| format!("Failed to serialize todos: {:?}", e))?;
-        
-        self.storage.set_item(&key, &json_str)
-            .map_err(|_| "Failed to save to local storage".to_string())
+    pub async fn get_all_todos(&self, list_id: Option<ListId>, offset: u64, limit: u64) -> Result<TodosPage, ClientError> {
+        let filter = list_id.map(|list_id| TodoFilter {
+            list_id: Some(list_id),
+            ..Default::default()
+        });
+        let arg = encode_one(PaginationInput {
+            offset,
+            limit,
+            cursor: None,
+            filter,
+            sort: Some(TodoSort {
+                field: SortField::Rank,
+                direction: SortDirection::Asc,
+            }),
+        }).map_err(transport_error)?;
+        match self.query("get_all_todos", arg).await? {
+            TodosResult::Ok(page) => Ok(page),
+            TodosResult::Err(err) => Err(err.into()),
+        }
     }
 
-    pub async fn add_todo(&self, text: String) -> Result<Todo, String> {
-        let mut todos = self.get_all_todos_from_storage();
-        let now = js_sys::Date::now() as u64;
-        
-        let new_todo = Todo {
-            id: self.get_next_id(),
-            text,
-            completed: false,
-            created_at: now,
-            updated_at: now,
-        };
-        
-        todos.push(new_todo.clone());
-        self.save_todos_to_storage(&todos)?;
-        
-        Ok(new_todo)
+    pub async fn update_todo(&self, input: UpdateTodoInput) -> Result<Todo, ClientError> {
+        let arg = encode_one(input).map_err(transport_error)?;
+        match self.update("update_todo", arg).await? {
+            TodoResult::Ok(todo) => Ok(todo),
+            TodoResult::Err(err) => Err(err.into()),
+        }
     }
 
-    pub async fn get_all_todos(&self, offset: u64, limit: u64) -> Result<TodosPage, String> {
-        let todos = self.get_all_todos_from_storage();
-        let total_count = todos.len() as u64;
-        
-        let start = offset as usize;
-        let end = std::cmp::min(start + limit as usize, todos.len());
-        
-        let page_todos = if start < todos.len() {
-            todos[start..end].to_vec()
-        } else {
-            Vec::new()
-        };
-        
-        let has_more = end < todos.len();
-        
-        Ok(TodosPage {
-            todos: page_todos,
-            total_count,
-            has_more,
-        })
+    pub async fn move_todo(&self, id: u64, before: Option<u64>, after: Option<u64>) -> Result<Todo, ClientError> {
+        let arg = encode_args((id, before, after)).map_err(transport_error)?;
+        match self.update("move_todo", arg).await? {
+            TodoResult::Ok(todo) => Ok(todo),
+            TodoResult::Err(err) => Err(err.into()),
+        }
     }
 
-    pub async fn update_todo_completed(&self, id: u64, completed: bool) -> Result<Todo, String> {
-        let mut todos = self.get_all_todos_from_storage();
-        
-        if let Some(todo) = todos.iter_mut().find(|t| t.id == id) {
-            todo.completed = completed;
-            todo.updated_at = js_sys::Date::now() as u64;
-            let updated_todo = todo.clone();
-            self.save_todos_to_storage(&todos)?;
-            Ok(updated_todo)
-        } else {
-            Err("Todo not found".to_string())
+    pub async fn delete_todo(&self, id: u64) -> Result<bool, ClientError> {
+        let arg = encode_one(id).map_err(transport_error)?;
+        match self.update("delete_todo", arg).await? {
+            DeleteResult::Ok(deleted) => Ok(deleted),
+            DeleteResult::Err(err) => Err(err.into()),
         }
     }
 
-    pub async fn delete_todo(&self, id: u64) -> Result<bool, String> {
-        let mut todos = self.get_all_todos_from_storage();
-        let initial_len = todos.len();
-        
-        todos.retain(|t| t.id != id);
-        
-        if todos.len() < initial_len {
-            self.save_todos_to_storage(&todos)?;
-            Ok(true)
-        } else {
-            Ok(false)
+    pub async fn get_todo_count(&self) -> Result<u64, ClientError> {
+        let arg = encode_args(()).map_err(transport_error)?;
+        self.query("get_todo_count", arg).await
+    }
+
+    pub async fn get_lists(&self) -> Result<Vec<TodoList>, ClientError> {
+        let arg = encode_one(false).map_err(transport_error)?;
+        self.query("get_lists", arg).await
+    }
+
+    pub async fn create_list(&self, name: String) -> Result<TodoList, ClientError> {
+        let input = CreateListInput {
+            name,
+            ..Default::default()
+        };
+        let arg = encode_one(input).map_err(transport_error)?;
+        match self.update("create_list", arg).await? {
+            ListResult::Ok(list) => Ok(list),
+            ListResult::Err(err) => Err(err.into()),
         }
     }
 
-    pub async fn get_todo_count(&self) -> Result<u64, String> {
-        let todos = self.get_all_todos_from_storage();
-        Ok(todos.len() as u64)
+    pub async fn list_shared_with_me(&self) -> Result<Vec<SharedList>, ClientError> {
+        let arg = encode_args(()).map_err(transport_error)?;
+        self.query("list_shared_with_me", arg).await
     }
-}
\ No newline at end of file
+
+    pub async fn get_notifications(&self) -> Result<Vec<Notification>, ClientError> {
+        let arg = encode_args(()).map_err(transport_error)?;
+        self.query("get_notifications", arg).await
+    }
+
+    pub async fn ack_notifications(&self, ids: Vec<NotificationId>) -> Result<u64, ClientError> {
+        let arg = encode_one(ids).map_err(transport_error)?;
+        self.update("ack_notifications", arg).await
+    }
+}
+
+fn transport_error(err: impl std::fmt::Display) -> ClientError {
+    ClientError::Transport(err.to_string())
+}
+
+fn decode_reply<R>(method: &str, bytes: &[u8]) -> Result<R, ClientError>
+where
+    R: CandidType + DeserializeOwned,
+{
+    decode_one(bytes)
+        .map_err(|e| ClientError::Transport(format!("Failed to decode {} reply: {}", method, e)))
+}
@@ -0,0 +1,67 @@
+use crate::types::ClientError;
+use ic_agent::identity::BasicIdentity;
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use web_sys::{window, Storage};
+
+/// localStorage key of the browser's PKCS#8-encoded Ed25519 key, as hex.
+const IDENTITY_KEY: &str = "todo_ic_identity";
+
+/// The identity this browser calls the canister as.
+///
+/// The canister refuses anonymous callers, so each browser gets its own
+/// Ed25519 key the first time it connects and keeps it in localStorage. The
+/// key never leaves the browser; clearing site data starts over with a new,
+/// empty principal.
+pub fn browser_identity() -> Result<BasicIdentity, ClientError> {
+    let storage = storage()?;
+    let stored = storage.get_item(IDENTITY_KEY)
+        .unwrap_or(None)
+        .and_then(|hex| decode_hex(&hex))
+        .and_then(|pkcs8| Ed25519KeyPair::from_pkcs8(&pkcs8).ok());
+    if let Some(key_pair) = stored {
+        return Ok(BasicIdentity::from_key_pair(key_pair));
+    }
+
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| ClientError::Transport("Failed to generate a key".to_string()))?;
+    storage.set_item(IDENTITY_KEY, &encode_hex(pkcs8.as_ref()))
+        .map_err(|_| ClientError::Transport("Failed to save the key to local storage".to_string()))?;
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|_| ClientError::Transport("Failed to load the generated key".to_string()))?;
+    Ok(BasicIdentity::from_key_pair(key_pair))
+}
+
+fn storage() -> Result<Storage, ClientError> {
+    let window = window().ok_or_else(|| ClientError::Transport("No window object".to_string()))?;
+    window.local_storage()
+        .map_err(|_| ClientError::Transport("Failed to get local storage".to_string()))?
+        .ok_or_else(|| ClientError::Transport("Local storage not available".to_string()))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips_and_rejects_garbage() {
+        let bytes = vec![0x00, 0x7f, 0xff, 0x10];
+        assert_eq!(decode_hex(&encode_hex(&bytes)), Some(bytes));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
@@ -2,8 +2,10 @@ use leptos::*;
 use wasm_bindgen::prelude::*;
 
 mod app;
+pub mod backend;
 mod components;
 mod ic_client;
+mod identity;
 mod types;
 
 pub use app::App;
@@ -1,56 +1,28 @@
-use candid::{CandidType, Deserialize};
-use serde::Serialize;
+use std::fmt;
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
-pub struct Todo {
-    pub id: u64,
-    pub text: String,
-    pub completed: bool,
-    pub created_at: u64,
-    pub updated_at: u64,
-}
-
-#[derive(CandidType, Deserialize, Debug)]
-pub struct CreateTodoInput {
-    pub text: String,
-}
-
-#[derive(CandidType, Deserialize, Debug)]
-pub struct UpdateTodoInput {
-    pub id: u64,
-    pub text: Option<String>,
-    pub completed: Option<bool>,
-}
-
-#[derive(CandidType, Deserialize, Debug)]
-pub struct PaginationInput {
-    pub offset: u64,
-    pub limit: u64,
-}
+pub use todo_ic_types::*;
 
-#[derive(CandidType, Serialize, Deserialize, Debug, PartialEq)]
-pub struct TodosPage {
-    pub todos: Vec<Todo>,
-    pub total_count: u64,
-    pub has_more: bool,
-}
-
-#[derive(CandidType, Serialize, Deserialize, Debug)]
-pub enum TodoResult {
-    Ok(Todo),
-    Err(String),
+/// Failure of a client call: either the request never produced an answer
+/// (`Transport`) or the canister answered with a `TodoError` (`Api`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientError {
+    Transport(String),
+    Api(TodoError),
 }
 
-#[derive(CandidType, Serialize, Deserialize, Debug)]
-pub enum TodosResult {
-    Ok(TodosPage),
-    Err(String),
+impl From<TodoError> for ClientError {
+    fn from(err: TodoError) -> Self {
+        ClientError::Api(err)
+    }
 }
 
-#[derive(CandidType, Serialize, Deserialize, Debug)]
-pub enum DeleteResult {
-    Ok(bool),
-    Err(String),
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(msg) => write!(f, "{}", msg),
+            ClientError::Api(err) => write!(f, "{}", err),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -58,6 +30,9 @@ pub enum Network {
     Local,
     Testnet,
     Mainnet,
+    /// Offline demo mode: todos live in the browser's localStorage and no
+    /// canister is contacted.
+    Demo,
 }
 
 impl Network {
@@ -66,6 +41,7 @@ impl Network {
             Network::Local => "http://127.0.0.1:4943",
             Network::Testnet => "https://ic0.app",
             Network::Mainnet => "https://ic0.app",
+            Network::Demo => "localStorage",
         }
     }
 
@@ -74,6 +50,7 @@ impl Network {
             Network::Local => "rrkah-fqaaa-aaaaa-aaaaq-cai", // Default local canister ID
             Network::Testnet => "25x2w-paaaa-aaaab-qackq-cai",
             Network::Mainnet => "vyggl-hpuoy-22jqe-6povn-6glf6-z2rle-wgphx-nyv6a-xbomq-e6knd-iae", // Replace when deployed
+            Network::Demo => "none (offline demo)",
         }
     }
 
@@ -82,6 +59,7 @@ impl Network {
             Network::Local => "Local",
             Network::Testnet => "Testnet",
             Network::Mainnet => "Mainnet",
+            Network::Demo => "Demo",
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,490 @@
+//! Candid types shared by `todo_ic_backend` and `todo_ic_frontend`.
+//!
+//! Both sides of the wire depend on this crate so the contract cannot drift.
+//! The `stable-structures` feature adds the `Storable` impls the canister
+//! needs to keep these types in stable memory.
+
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use std::fmt;
+
+pub mod rank;
+pub mod recurrence;
+#[cfg(feature = "stable-structures")]
+mod storable;
+
+pub type TodoId = u64;
+pub type ListId = u64;
+pub type NotificationId = u64;
+
+/// Longest todo text the stable encoding has room for, and the default
+/// `Config::max_todo_text_length`.
+pub const MAX_TODO_TEXT_LENGTH: usize = 1000;
+pub const MAX_TAGS_PER_TODO: usize = 10;
+/// Longest tag in bytes, after trimming.
+pub const MAX_TAG_LENGTH: usize = 32;
+pub const MAX_LIST_NAME_LENGTH: usize = 100;
+pub const DEFAULT_LIST_COLOUR: &str = "#4c51bf";
+/// Deepest level a subtask may sit at; top-level todos are at depth 0.
+pub const MAX_SUBTASK_DEPTH: usize = 5;
+/// Longest rank string before a list's ranks are respaced.
+pub const MAX_RANK_LENGTH: usize = 64;
+/// Longest gap `Recurrence::EveryNDays` may leave between occurrences.
+pub const MAX_RECURRENCE_INTERVAL_DAYS: u32 = 366;
+pub const DEFAULT_MAX_PAGE_LIMIT: u64 = 100;
+pub const DEFAULT_MAX_TODOS_PER_OWNER: u64 = 10_000;
+pub const DEFAULT_MAX_BATCH_SIZE: u64 = 100;
+pub const DEFAULT_TRASH_RETENTION_NANOS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+pub const DEFAULT_MAX_BYTES_PER_OWNER: u64 = 5_000_000;
+pub const DEFAULT_RATE_LIMIT_PER_MINUTE: u64 = 60;
+pub const DEFAULT_RATE_LIMIT_BURST: u64 = 100;
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// When the next occurrence of a recurring todo is due, counted in UTC
+/// calendar days from the current occurrence's `due_at`, which keeps its time
+/// of day. See `recurrence`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Recurrence {
+    Daily,
+    /// On each of `weekdays`.
+    Weekly {
+        weekdays: Vec<Weekday>,
+    },
+    /// On `day` of every month, or on the last day of shorter months.
+    Monthly {
+        day: u8,
+    },
+    EveryNDays {
+        days: u32,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Todo {
+    pub id: TodoId,
+    #[serde(default = "Principal::anonymous")]
+    pub owner: Principal,
+    pub text: String,
+    pub completed: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    /// Nanosecond timestamp the todo is due by.
+    #[serde(default)]
+    pub due_at: Option<u64>,
+    /// Lower-cased, sorted and free of duplicates.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The list the todo belongs to, if any.
+    #[serde(default)]
+    pub list_id: Option<ListId>,
+    /// The todo this is a subtask of, if any.
+    #[serde(default)]
+    pub parent_id: Option<TodoId>,
+    /// Position in the user's manual order of its list; see `rank`.
+    #[serde(default)]
+    pub rank: String,
+    /// Set on the newest occurrence of a recurring todo. The rule moves to
+    /// the next occurrence once that is created.
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Nanosecond timestamp to remind the owner at. Cleared once the
+    /// reminder has been delivered to their notification inbox.
+    #[serde(default)]
+    pub remind_at: Option<u64>,
+    /// Number of edits made to the todo. Unlike `updated_at`, it tells apart
+    /// edits made in the same round, so clients pass it back as
+    /// `UpdateTodoInput.expected_version`.
+    #[serde(default)]
+    pub version: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CreateTodoInput {
+    pub text: String,
+    pub priority: Option<Priority>,
+    pub due_at: Option<u64>,
+    pub tags: Option<Vec<String>>,
+    pub list_id: Option<ListId>,
+    /// Requires `due_at`, which the first occurrence is due at.
+    pub recurrence: Option<Recurrence>,
+    pub remind_at: Option<u64>,
+}
+
+/// Unset fields are left unchanged. `priority`, `due_at`, `list_id`,
+/// `recurrence` and `remind_at` are cleared by sending `opt null`, and `tags`
+/// by sending an empty list.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct UpdateTodoInput {
+    pub id: TodoId,
+    pub text: Option<String>,
+    pub completed: Option<bool>,
+    pub priority: Option<Option<Priority>>,
+    pub due_at: Option<Option<u64>>,
+    pub tags: Option<Vec<String>>,
+    pub list_id: Option<Option<ListId>>,
+    pub recurrence: Option<Option<Recurrence>>,
+    pub remind_at: Option<Option<u64>>,
+    /// The `version` the client last saw. When set, the update is rejected
+    /// with `TodoError::Conflict` if the todo has changed since.
+    pub expected_version: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PaginationInput {
+    pub offset: u64,
+    pub limit: u64,
+    /// `next_cursor` from the previous page. Paging by cursor stays stable
+    /// while todos are added; `offset` is applied after the cursor.
+    pub cursor: Option<String>,
+    pub filter: Option<TodoFilter>,
+    /// Defaults to newest first. Cursors are only issued for that order.
+    pub sort: Option<TodoSort>,
+}
+
+/// Conditions a todo must meet to be listed. Unset fields match everything;
+/// time bounds are inclusive nanosecond timestamps.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TodoFilter {
+    pub completed: Option<bool>,
+    pub created_after: Option<u64>,
+    pub created_before: Option<u64>,
+    pub updated_after: Option<u64>,
+    pub updated_before: Option<u64>,
+    /// Case-insensitive substring of the todo text.
+    pub text_contains: Option<String>,
+    pub priority: Option<Priority>,
+    /// Only todos with a due date are matched when either bound is set.
+    pub due_after: Option<u64>,
+    pub due_before: Option<u64>,
+    pub tag: Option<String>,
+    pub list_id: Option<ListId>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortField {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    Id,
+    Text,
+    /// The user's manual order, grouped by list.
+    Rank,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TodoSort {
+    pub field: SortField,
+    pub direction: SortDirection,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TodosPage {
+    pub todos: Vec<Todo>,
+    pub total_count: u64,
+    pub has_more: bool,
+    pub next_cursor: Option<String>,
+}
+
+/// A todo with its direct subtasks. `completion_percent` covers every
+/// subtask below the todo, however deep; a todo without subtasks is 0 or 100
+/// depending on its own `completed`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TodoWithSubtasks {
+    pub todo: Todo,
+    pub subtasks: Vec<Todo>,
+    pub completion_percent: u8,
+}
+
+/// A named group of todos, such as a project. `colour` is a `#rrggbb` hex
+/// string.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TodoList {
+    pub id: ListId,
+    pub owner: Principal,
+    pub name: String,
+    pub colour: String,
+    pub archived: bool,
+    pub created_at: u64,
+}
+
+/// `colour` defaults to `DEFAULT_LIST_COLOUR`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CreateListInput {
+    pub name: String,
+    pub colour: Option<String>,
+}
+
+/// Unset fields are left unchanged.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct UpdateListInput {
+    pub id: ListId,
+    pub name: Option<String>,
+    pub colour: Option<String>,
+    pub archived: Option<bool>,
+}
+
+/// Access to a shared list, each role including the ones before it. Viewers
+/// can read the list's todos, editors can also add, change, reorder and
+/// delete them, and owners can also change the list and its shares. A list's
+/// creator is always an owner.
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum ListRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+/// One principal's access to a list.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ListShare {
+    pub list_id: ListId,
+    pub principal: Principal,
+    pub role: ListRole,
+}
+
+/// A list someone else created that is shared with the caller.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SharedList {
+    pub list: TodoList,
+    pub role: ListRole,
+}
+
+/// A reminder that came due, kept in its owner's inbox until acknowledged.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    pub id: NotificationId,
+    pub owner: Principal,
+    pub todo_id: TodoId,
+    /// The todo's text when the reminder was delivered.
+    pub text: String,
+    pub remind_at: u64,
+    pub delivered_at: u64,
+}
+
+/// Argument of the canister's `init` and `post_upgrade`. Every principal in
+/// `admins` is made an admin, alongside the canister's controllers, and
+/// `config` replaces the stored `Config`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InitArgs {
+    pub admins: Vec<Principal>,
+    pub config: Option<Config>,
+    /// Takes over the todos and lists of the anonymous principal. Todos
+    /// stored before todos had owners belong to it, and anonymous callers
+    /// are refused unless `Config::allow_anonymous` is set.
+    pub legacy_owner: Option<Principal>,
+}
+
+/// Limits admins can change without a new wasm.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Longest todo text in bytes, at most `MAX_TODO_TEXT_LENGTH`.
+    pub max_todo_text_length: u64,
+    /// Largest `PaginationInput::limit`.
+    pub max_page_limit: u64,
+    /// Most live todos a principal may own; trashed todos do not count.
+    pub max_todos_per_owner: u64,
+    /// Most items in one batch call.
+    pub max_batch_size: u64,
+    /// How long a deleted todo stays restorable before it is purged.
+    pub trash_retention_nanos: u64,
+    /// Most bytes of todo text and tags a principal may store in live todos.
+    pub max_bytes_per_owner: u64,
+    /// Update calls a principal regains per minute; zero turns rate limiting
+    /// off.
+    pub rate_limit_per_minute: u64,
+    /// Update calls a principal may make in a burst after being idle.
+    pub rate_limit_burst: u64,
+    /// Whether the anonymous principal may call anything but admin endpoints.
+    pub allow_anonymous: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_todo_text_length: MAX_TODO_TEXT_LENGTH as u64,
+            max_page_limit: DEFAULT_MAX_PAGE_LIMIT,
+            max_todos_per_owner: DEFAULT_MAX_TODOS_PER_OWNER,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            trash_retention_nanos: DEFAULT_TRASH_RETENTION_NANOS,
+            max_bytes_per_owner: DEFAULT_MAX_BYTES_PER_OWNER,
+            rate_limit_per_minute: DEFAULT_RATE_LIMIT_PER_MINUTE,
+            rate_limit_burst: DEFAULT_RATE_LIMIT_BURST,
+            allow_anonymous: false,
+        }
+    }
+}
+
+/// Canister-wide counts for admins.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GlobalStats {
+    pub todos: u64,
+    /// Principals owning at least one live todo.
+    pub owners: u64,
+    pub lists: u64,
+    pub shares: u64,
+    pub trashed_todos: u64,
+    pub notifications: u64,
+    pub admins: u64,
+    pub banned: u64,
+    pub stable_memory_bytes: u64,
+    pub cycles: u128,
+}
+
+/// A timer job an admin can run on demand, for example after an upgrade
+/// left it behind.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    PurgeExpiredTrash,
+    MaterialiseRecurringTodos,
+    DeliverReminders,
+}
+
+/// A deleted todo kept in the trash until it is restored or purged.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TrashedTodo {
+    pub todo: Todo,
+    pub deleted_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrashPage {
+    pub items: Vec<TrashedTodo>,
+    pub total_count: u64,
+    pub has_more: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TodoError {
+    NotFound { id: TodoId },
+    Validation { field: String, reason: String },
+    Unauthorized,
+    QuotaExceeded { quota: String, limit: u64 },
+    Conflict { id: TodoId, version: u64 },
+}
+
+impl TodoError {
+    pub fn validation(field: &str, reason: impl Into<String>) -> Self {
+        TodoError::Validation {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TodoError::NotFound { id } => write!(f, "Todo {} not found", id),
+            TodoError::Validation { field, reason } => write!(f, "Invalid {}: {}", field, reason),
+            TodoError::Unauthorized => write!(f, "Caller is not authorized for this todo"),
+            TodoError::QuotaExceeded { quota, limit } => {
+                write!(f, "Quota exceeded: {} is limited to {}", quota, limit)
+            }
+            TodoError::Conflict { id, .. } => {
+                write!(f, "Todo {} was changed elsewhere; reload and try again", id)
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TodoResult {
+    Ok(Todo),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TodosResult {
+    Ok(TodosPage),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DeleteResult {
+    Ok(bool),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SubtasksResult {
+    Ok(Box<TodoWithSubtasks>),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ListResult {
+    Ok(TodoList),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ConfigResult {
+    Ok(Config),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ShareResult {
+    Ok(ListShare),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SharesResult {
+    Ok(Vec<ListShare>),
+    Err(TodoError),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TrashResult {
+    Ok(TrashPage),
+    Err(TodoError),
+}
+
+/// Outcome of a batch create or update: one `TodoResult` per input, in
+/// input order, or a single error when the batch itself is rejected.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TodoBatchResult {
+    Ok(Vec<TodoResult>),
+    Err(TodoError),
+}
+
+/// Outcome of a batch delete: one `DeleteResult` per id, in input order, or
+/// a single error when the batch itself is rejected.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DeleteBatchResult {
+    Ok(Vec<DeleteResult>),
+    Err(TodoError),
+}
@@ -0,0 +1,119 @@
+//! Rank strings for manual ordering.
+//!
+//! A rank is a non-empty string of base-62 digits (`0-9A-Za-z`, in ASCII
+//! order) that never ends in `0`. Ranks compare as plain strings, so a todo
+//! can be placed between two neighbours by writing only its own rank.
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = DIGITS.len();
+
+fn digit_value(byte: u8) -> usize {
+    DIGITS
+        .iter()
+        .position(|&digit| digit == byte)
+        .expect("rank contains a character outside the base-62 alphabet")
+}
+
+/// A rank strictly between `before` and `after`, where `None` means the
+/// start or end of the list. `before` must sort below `after`.
+///
+/// Inserting at either end steps by a single digit rather than halving the
+/// gap, so ranks grow by one character per ~60 insertions at the same end.
+pub fn rank_between(before: Option<&str>, after: Option<&str>) -> String {
+    let low = before.unwrap_or("").as_bytes();
+    let mut high = after.map(str::as_bytes);
+    let mut rank = String::new();
+
+    for position in 0.. {
+        let l = low.get(position).map_or(0, |&byte| digit_value(byte));
+        // Once the rank sorts below `after`'s prefix, the rest is unbounded.
+        let h = high.map_or(BASE, |high| {
+            high.get(position).map_or(BASE, |&byte| digit_value(byte))
+        });
+
+        if h > l + 1 {
+            let digit = match (before, high) {
+                (None, None) if after.is_none() => (l + h) / 2,
+                (None, _) => h - 1,
+                (Some(_), None) => l + 1,
+                (Some(_), Some(_)) => (l + h) / 2,
+            };
+            rank.push(DIGITS[digit] as char);
+            return rank;
+        }
+
+        rank.push(DIGITS[l] as char);
+        if h > l {
+            high = None;
+        }
+    }
+    unreachable!("a rank is found within a few digits past the longer bound")
+}
+
+/// `count` ranks in ascending order, spread evenly so every gap leaves room
+/// for later insertions. Used to assign fresh ranks to a whole list.
+pub fn evenly_spaced_ranks(count: usize) -> Vec<String> {
+    let slots = count as u128 + 1;
+    let mut width = 1;
+    let mut capacity = BASE as u128;
+    while capacity < slots * BASE as u128 {
+        width += 1;
+        capacity *= BASE as u128;
+    }
+    let step = capacity / slots;
+
+    (1..=count as u128)
+        .map(|slot| encode(slot * step, width))
+        .collect()
+}
+
+/// `value` as `width` base-62 digits with trailing zeros dropped, which keeps
+/// the string order of the padded form.
+fn encode(mut value: u128, width: usize) -> String {
+    let mut digits = vec![DIGITS[0]; width];
+    for digit in digits.iter_mut().rev() {
+        *digit = DIGITS[(value % BASE as u128) as usize];
+        value /= BASE as u128;
+    }
+    while digits.last() == Some(&DIGITS[0]) {
+        digits.pop();
+    }
+    String::from_utf8(digits).expect("rank digits are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_fall_strictly_between_their_bounds() {
+        let first = rank_between(None, None);
+        let top = rank_between(None, Some(&first));
+        let bottom = rank_between(Some(&first), None);
+        let middle = rank_between(Some(&top), Some(&first));
+        assert!(top < middle && middle < first && first < bottom);
+
+        // Adjacent digits force a longer rank.
+        let between = rank_between(Some("a"), Some("b"));
+        assert!("a" < between.as_str() && between.as_str() < "b");
+    }
+
+    #[test]
+    fn repeated_insertions_at_the_top_grow_slowly() {
+        let mut ranks = vec![rank_between(None, None)];
+        for _ in 0..500 {
+            let rank = rank_between(None, Some(&ranks[0]));
+            assert!(rank < ranks[0] && !rank.ends_with('0'));
+            ranks.insert(0, rank);
+        }
+        assert!(ranks[0].len() <= 10);
+    }
+
+    #[test]
+    fn evenly_spaced_ranks_ascend() {
+        let ranks = evenly_spaced_ranks(1000);
+        assert_eq!(ranks.len(), 1000);
+        assert!(ranks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ranks.iter().all(|rank| !rank.ends_with('0')));
+    }
+}
@@ -0,0 +1,162 @@
+//! Due dates of recurring todos.
+//!
+//! Occurrences are counted in UTC calendar days. The next occurrence keeps
+//! the time of day of the current one, so a report due at 09:00 on a Monday
+//! stays due at 09:00.
+
+use crate::{Recurrence, Weekday};
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Days from 1970-01-01 to the civil date 1 March 0000, which starts the
+/// 400-year cycles the conversions below count in.
+const EPOCH_SHIFT_DAYS: u64 = 719_468;
+const DAYS_PER_ERA: u64 = 146_097;
+
+/// Due date of the occurrence after the one due at `due_at`, always on a
+/// later day. Both are nanosecond timestamps.
+pub fn next_occurrence(due_at: u64, rule: &Recurrence) -> u64 {
+    let day = due_at / NANOS_PER_DAY;
+    let time_of_day = due_at % NANOS_PER_DAY;
+
+    let next_day = match rule {
+        Recurrence::Daily => day + 1,
+        Recurrence::EveryNDays { days } => day + u64::from((*days).max(1)),
+        // An empty weekday list repeats on the same weekday.
+        Recurrence::Weekly { weekdays } => (day + 1..=day + 7)
+            .find(|&next| weekdays.contains(&weekday(next)))
+            .unwrap_or(day + 7),
+        Recurrence::Monthly { day: day_of_month } => {
+            let day_of_month = u64::from((*day_of_month).max(1));
+            let (year, month, current) = civil_from_days(day);
+            let this_month = day_of_month.min(days_in_month(year, month));
+            if this_month > current {
+                days_from_civil(year, month, this_month)
+            } else {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                days_from_civil(year, month, day_of_month.min(days_in_month(year, month)))
+            }
+        }
+    };
+
+    next_day
+        .saturating_mul(NANOS_PER_DAY)
+        .saturating_add(time_of_day)
+}
+
+fn weekday(day: u64) -> Weekday {
+    // 1970-01-01 was a Thursday.
+    match (day + 3) % 7 {
+        0 => Weekday::Monday,
+        1 => Weekday::Tuesday,
+        2 => Weekday::Wednesday,
+        3 => Weekday::Thursday,
+        4 => Weekday::Friday,
+        5 => Weekday::Saturday,
+        _ => Weekday::Sunday,
+    }
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// `(year, month, day)` of a day counted from 1970-01-01, with months and
+/// days starting at 1.
+fn civil_from_days(day: u64) -> (u64, u64, u64) {
+    let shifted = day + EPOCH_SHIFT_DAYS;
+    let era = shifted / DAYS_PER_ERA;
+    let day_of_era = shifted % DAYS_PER_ERA;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months are counted from March so the leap day falls at the end.
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day_of_month)
+}
+
+/// Inverse of `civil_from_days` for dates from 1970 onwards.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * DAYS_PER_ERA + day_of_era - EPOCH_SHIFT_DAYS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NINE_AM: u64 = 9 * 60 * 60 * 1_000_000_000;
+
+    fn at_nine(year: u64, month: u64, day: u64) -> u64 {
+        days_from_civil(year, month, day) * NANOS_PER_DAY + NINE_AM
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(civil_from_days(days_from_civil(2100, 3, 1)), (2100, 3, 1));
+        assert_eq!(weekday(days_from_civil(2026, 10, 19)), Weekday::Monday);
+    }
+
+    #[test]
+    fn daily_and_every_n_days_keep_the_time_of_day() {
+        let monday = at_nine(2026, 10, 19);
+        assert_eq!(
+            next_occurrence(monday, &Recurrence::Daily),
+            at_nine(2026, 10, 20)
+        );
+        assert_eq!(
+            next_occurrence(monday, &Recurrence::EveryNDays { days: 14 }),
+            at_nine(2026, 11, 2)
+        );
+    }
+
+    #[test]
+    fn weekly_moves_to_the_next_listed_weekday() {
+        let rule = Recurrence::Weekly {
+            weekdays: vec![Weekday::Monday, Weekday::Thursday],
+        };
+        let monday = at_nine(2026, 10, 19);
+        let thursday = next_occurrence(monday, &rule);
+        assert_eq!(thursday, at_nine(2026, 10, 22));
+        assert_eq!(next_occurrence(thursday, &rule), at_nine(2026, 10, 26));
+    }
+
+    #[test]
+    fn monthly_falls_back_to_the_last_day_of_short_months() {
+        let rule = Recurrence::Monthly { day: 31 };
+        let january = next_occurrence(at_nine(2024, 1, 15), &rule);
+        assert_eq!(january, at_nine(2024, 1, 31));
+        let february = next_occurrence(january, &rule);
+        assert_eq!(february, at_nine(2024, 2, 29));
+        assert_eq!(next_occurrence(february, &rule), at_nine(2024, 3, 31));
+        assert_eq!(
+            next_occurrence(at_nine(2026, 12, 31), &rule),
+            at_nine(2027, 1, 31)
+        );
+    }
+}
@@ -0,0 +1,604 @@
+use crate::{
+    Config, ListId, ListRole, Notification, Priority, Recurrence, Todo, TodoId, TodoList,
+    TrashedTodo, MAX_LIST_NAME_LENGTH, MAX_RANK_LENGTH, MAX_TAGS_PER_TODO, MAX_TAG_LENGTH,
+    MAX_TODO_TEXT_LENGTH,
+};
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use std::borrow::Cow;
+
+/// Layout version written as the first byte of every encoded `Todo`.
+/// Version 4 is the Candid encoding of the struct, and versions 3, 2 and 1
+/// the encodings of `TodoV3`, `TodoV2` and `TodoV1`; records without a known
+/// version byte are legacy JSON written before versioning existed. Candid
+/// decodes a missing `opt` field as `None`, so adding one (like `list_id`)
+/// needs no new version.
+const TODO_ENCODING_VERSION: u8 = 4;
+const TODO_ENCODING_VERSION_V3: u8 = 3;
+const TODO_ENCODING_VERSION_V2: u8 = 2;
+const TODO_ENCODING_VERSION_V1: u8 = 1;
+
+/// Room reserved for everything except the text and tags: the version byte,
+/// the Candid header and type table, fixed-width fields and the owner principal.
+const TODO_ENCODING_OVERHEAD: u32 = 384;
+
+/// Largest encoding of the tag list: a length prefix per tag plus its bytes.
+const TODO_TAGS_MAX_SIZE: u32 = (MAX_TAGS_PER_TODO * (MAX_TAG_LENGTH + 1)) as u32;
+
+/// Largest encoding of a recurrence rule, including the type table entries
+/// for `Recurrence` and `Weekday` and a weekly rule listing every weekday.
+const TODO_RECURRENCE_MAX_SIZE: u32 = 128;
+
+const TODO_MAX_SIZE: u32 = MAX_TODO_TEXT_LENGTH as u32
+    + TODO_ENCODING_OVERHEAD
+    + TODO_TAGS_MAX_SIZE
+    + MAX_RANK_LENGTH as u32
+    + TODO_RECURRENCE_MAX_SIZE;
+
+/// `Todo` as stored under encoding version 1, before priority, due date and
+/// tags. Candid only fills in missing `opt` fields, so these records are
+/// decoded through this shape.
+#[derive(CandidType, Deserialize)]
+struct TodoV1 {
+    id: u64,
+    owner: Principal,
+    text: String,
+    completed: bool,
+    created_at: u64,
+    updated_at: u64,
+}
+
+impl From<TodoV1> for Todo {
+    fn from(todo: TodoV1) -> Self {
+        Todo {
+            id: todo.id,
+            owner: todo.owner,
+            text: todo.text,
+            completed: todo.completed,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            priority: None,
+            due_at: None,
+            tags: Vec::new(),
+            list_id: None,
+            parent_id: None,
+            rank: String::new(),
+            recurrence: None,
+            remind_at: None,
+            version: 0,
+        }
+    }
+}
+
+/// `Todo` as stored under encoding version 2, before manual ordering. The
+/// rank is left empty and assigned by a migration.
+#[derive(CandidType, Deserialize)]
+struct TodoV2 {
+    id: TodoId,
+    owner: Principal,
+    text: String,
+    completed: bool,
+    created_at: u64,
+    updated_at: u64,
+    priority: Option<Priority>,
+    due_at: Option<u64>,
+    tags: Vec<String>,
+    list_id: Option<ListId>,
+    parent_id: Option<TodoId>,
+}
+
+impl From<TodoV2> for Todo {
+    fn from(todo: TodoV2) -> Self {
+        Todo {
+            id: todo.id,
+            owner: todo.owner,
+            text: todo.text,
+            completed: todo.completed,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            priority: todo.priority,
+            due_at: todo.due_at,
+            tags: todo.tags,
+            list_id: todo.list_id,
+            parent_id: todo.parent_id,
+            rank: String::new(),
+            recurrence: None,
+            remind_at: None,
+            version: 0,
+        }
+    }
+}
+
+/// `Todo` as stored under encoding version 3, before the edit counter. The
+/// version starts at 0.
+#[derive(CandidType, Deserialize)]
+struct TodoV3 {
+    id: TodoId,
+    owner: Principal,
+    text: String,
+    completed: bool,
+    created_at: u64,
+    updated_at: u64,
+    priority: Option<Priority>,
+    due_at: Option<u64>,
+    tags: Vec<String>,
+    list_id: Option<ListId>,
+    parent_id: Option<TodoId>,
+    rank: String,
+    recurrence: Option<Recurrence>,
+    remind_at: Option<u64>,
+}
+
+impl From<TodoV3> for Todo {
+    fn from(todo: TodoV3) -> Self {
+        Todo {
+            id: todo.id,
+            owner: todo.owner,
+            text: todo.text,
+            completed: todo.completed,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            priority: todo.priority,
+            due_at: todo.due_at,
+            tags: todo.tags,
+            list_id: todo.list_id,
+            parent_id: todo.parent_id,
+            rank: todo.rank,
+            recurrence: todo.recurrence,
+            remind_at: todo.remind_at,
+            version: 0,
+        }
+    }
+}
+
+impl Storable for Todo {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![TODO_ENCODING_VERSION];
+        bytes.extend(Encode!(self).expect("failed to encode Todo"));
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes.first() {
+            Some(&TODO_ENCODING_VERSION) => {
+                Decode!(&bytes[1..], Todo).expect("failed to decode Todo")
+            }
+            Some(&TODO_ENCODING_VERSION_V3) => Decode!(&bytes[1..], TodoV3)
+                .expect("failed to decode version 3 Todo")
+                .into(),
+            Some(&TODO_ENCODING_VERSION_V2) => Decode!(&bytes[1..], TodoV2)
+                .expect("failed to decode version 2 Todo")
+                .into(),
+            Some(&TODO_ENCODING_VERSION_V1) => Decode!(&bytes[1..], TodoV1)
+                .expect("failed to decode version 1 Todo")
+                .into(),
+            _ => serde_json::from_slice(&bytes).expect("failed to decode legacy JSON Todo"),
+        }
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: TODO_MAX_SIZE,
+        is_fixed_size: false,
+    };
+}
+
+/// Layout version of an encoded `TrashedTodo`: version byte, then Candid.
+/// Versions 3, 2 and 1 wrap a `TodoV3`, a `TodoV2` and a `TodoV1`.
+const TRASHED_TODO_ENCODING_VERSION: u8 = 4;
+const TRASHED_TODO_ENCODING_VERSION_V3: u8 = 3;
+const TRASHED_TODO_ENCODING_VERSION_V2: u8 = 2;
+const TRASHED_TODO_ENCODING_VERSION_V1: u8 = 1;
+
+#[derive(CandidType, Deserialize)]
+struct TrashedTodoV3 {
+    todo: TodoV3,
+    deleted_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct TrashedTodoV2 {
+    todo: TodoV2,
+    deleted_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct TrashedTodoV1 {
+    todo: TodoV1,
+    deleted_at: u64,
+}
+
+impl Storable for TrashedTodo {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![TRASHED_TODO_ENCODING_VERSION];
+        bytes.extend(Encode!(self).expect("failed to encode TrashedTodo"));
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes.first() {
+            Some(&TRASHED_TODO_ENCODING_VERSION) => {
+                Decode!(&bytes[1..], TrashedTodo).expect("failed to decode TrashedTodo")
+            }
+            Some(&TRASHED_TODO_ENCODING_VERSION_V3) => {
+                let trashed = Decode!(&bytes[1..], TrashedTodoV3)
+                    .expect("failed to decode version 3 TrashedTodo");
+                TrashedTodo {
+                    todo: trashed.todo.into(),
+                    deleted_at: trashed.deleted_at,
+                }
+            }
+            Some(&TRASHED_TODO_ENCODING_VERSION_V2) => {
+                let trashed = Decode!(&bytes[1..], TrashedTodoV2)
+                    .expect("failed to decode version 2 TrashedTodo");
+                TrashedTodo {
+                    todo: trashed.todo.into(),
+                    deleted_at: trashed.deleted_at,
+                }
+            }
+            Some(&TRASHED_TODO_ENCODING_VERSION_V1) => {
+                let trashed = Decode!(&bytes[1..], TrashedTodoV1)
+                    .expect("failed to decode version 1 TrashedTodo");
+                TrashedTodo {
+                    todo: trashed.todo.into(),
+                    deleted_at: trashed.deleted_at,
+                }
+            }
+            version => panic!("unknown TrashedTodo encoding version {:?}", version),
+        }
+    }
+
+    // The wrapper adds a record field and a timestamp to an encoded Todo.
+    const BOUND: Bound = Bound::Bounded {
+        max_size: TODO_MAX_SIZE + 64,
+        is_fixed_size: false,
+    };
+}
+
+/// Layout version of an encoded `TodoList`: version byte, then Candid.
+const TODO_LIST_ENCODING_VERSION: u8 = 1;
+
+impl Storable for TodoList {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![TODO_LIST_ENCODING_VERSION];
+        bytes.extend(Encode!(self).expect("failed to encode TodoList"));
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes.first() {
+            Some(&TODO_LIST_ENCODING_VERSION) => {
+                Decode!(&bytes[1..], TodoList).expect("failed to decode TodoList")
+            }
+            version => panic!("unknown TodoList encoding version {:?}", version),
+        }
+    }
+
+    // Everything but the name: version byte, Candid header and type table,
+    // fixed-width fields, the owner principal and a `#rrggbb` colour.
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_LIST_NAME_LENGTH as u32 + 256,
+        is_fixed_size: false,
+    };
+}
+
+// A single tag byte. New roles must take new values; existing ones never change.
+impl Storable for ListRole {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let tag = match self {
+            ListRole::Viewer => 0,
+            ListRole::Editor => 1,
+            ListRole::Owner => 2,
+        };
+        Cow::Owned(vec![tag])
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes.first() {
+            Some(0) => ListRole::Viewer,
+            Some(1) => ListRole::Editor,
+            Some(2) => ListRole::Owner,
+            tag => panic!("unknown ListRole tag {:?}", tag),
+        }
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1,
+        is_fixed_size: true,
+    };
+}
+
+/// Layout version of an encoded `Notification`: version byte, then Candid.
+const NOTIFICATION_ENCODING_VERSION: u8 = 1;
+
+impl Storable for Notification {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![NOTIFICATION_ENCODING_VERSION];
+        bytes.extend(Encode!(self).expect("failed to encode Notification"));
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes.first() {
+            Some(&NOTIFICATION_ENCODING_VERSION) => {
+                Decode!(&bytes[1..], Notification).expect("failed to decode Notification")
+            }
+            version => panic!("unknown Notification encoding version {:?}", version),
+        }
+    }
+
+    // Everything but the text: version byte, Candid header and type table,
+    // fixed-width fields and the owner principal.
+    const BOUND: Bound = Bound::Bounded {
+        max_size: MAX_TODO_TEXT_LENGTH as u32 + 256,
+        is_fixed_size: false,
+    };
+}
+
+/// Layout versions of an encoded `Config`: version byte, then Candid.
+/// Versions 1 to 3 were named `Limits`: version 1 held only the batch size
+/// limit, version 2 added the trash retention and version 3 the todo quota.
+/// Version 4 had no byte quota, rate limit or anonymous access switch.
+const CONFIG_ENCODING_VERSION_V1: u8 = 1;
+const CONFIG_ENCODING_VERSION_V2: u8 = 2;
+const CONFIG_ENCODING_VERSION_V3: u8 = 3;
+const CONFIG_ENCODING_VERSION_V4: u8 = 4;
+const CONFIG_ENCODING_VERSION: u8 = 5;
+
+#[derive(CandidType, Deserialize)]
+struct ConfigV1 {
+    max_batch_size: u64,
+}
+
+impl From<ConfigV1> for Config {
+    fn from(v1: ConfigV1) -> Self {
+        Config {
+            max_batch_size: v1.max_batch_size,
+            ..Config::default()
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct ConfigV2 {
+    max_batch_size: u64,
+    trash_retention_nanos: u64,
+}
+
+impl From<ConfigV2> for Config {
+    fn from(v2: ConfigV2) -> Self {
+        Config {
+            max_batch_size: v2.max_batch_size,
+            trash_retention_nanos: v2.trash_retention_nanos,
+            ..Config::default()
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct ConfigV3 {
+    max_batch_size: u64,
+    trash_retention_nanos: u64,
+    max_todos_per_owner: u64,
+}
+
+impl From<ConfigV3> for Config {
+    fn from(v3: ConfigV3) -> Self {
+        Config {
+            max_batch_size: v3.max_batch_size,
+            trash_retention_nanos: v3.trash_retention_nanos,
+            max_todos_per_owner: v3.max_todos_per_owner,
+            ..Config::default()
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct ConfigV4 {
+    max_todo_text_length: u64,
+    max_page_limit: u64,
+    max_todos_per_owner: u64,
+    max_batch_size: u64,
+    trash_retention_nanos: u64,
+}
+
+impl From<ConfigV4> for Config {
+    fn from(v4: ConfigV4) -> Self {
+        Config {
+            max_todo_text_length: v4.max_todo_text_length,
+            max_page_limit: v4.max_page_limit,
+            max_todos_per_owner: v4.max_todos_per_owner,
+            max_batch_size: v4.max_batch_size,
+            trash_retention_nanos: v4.trash_retention_nanos,
+            ..Config::default()
+        }
+    }
+}
+
+impl Storable for Config {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = vec![CONFIG_ENCODING_VERSION];
+        bytes.extend(Encode!(self).expect("failed to encode Config"));
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes.first() {
+            Some(&CONFIG_ENCODING_VERSION) => {
+                Decode!(&bytes[1..], Config).expect("failed to decode Config")
+            }
+            Some(&CONFIG_ENCODING_VERSION_V4) => Decode!(&bytes[1..], ConfigV4)
+                .expect("failed to decode version 4 Config")
+                .into(),
+            Some(&CONFIG_ENCODING_VERSION_V3) => Decode!(&bytes[1..], ConfigV3)
+                .expect("failed to decode version 3 Config")
+                .into(),
+            Some(&CONFIG_ENCODING_VERSION_V2) => Decode!(&bytes[1..], ConfigV2)
+                .expect("failed to decode version 2 Config")
+                .into(),
+            Some(&CONFIG_ENCODING_VERSION_V1) => Decode!(&bytes[1..], ConfigV1)
+                .expect("failed to decode version 1 Config")
+                .into(),
+            version => panic!("unknown Config encoding version {:?}", version),
+        }
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Weekday, DEFAULT_RATE_LIMIT_BURST};
+
+    #[test]
+    fn version_1_records_decode_without_new_fields() {
+        let v1 = TodoV1 {
+            id: 7,
+            owner: Principal::anonymous(),
+            text: "Old todo".to_string(),
+            completed: true,
+            created_at: 1,
+            updated_at: 2,
+        };
+        let mut bytes = vec![TODO_ENCODING_VERSION_V1];
+        bytes.extend(Encode!(&v1).unwrap());
+
+        let todo = Todo::from_bytes(Cow::Owned(bytes));
+        assert_eq!(todo.text, "Old todo");
+        assert_eq!(todo.priority, None);
+        assert_eq!(todo.due_at, None);
+        assert!(todo.tags.is_empty());
+    }
+
+    #[test]
+    fn version_2_records_decode_without_a_rank() {
+        let v2 = TodoV2 {
+            id: 8,
+            owner: Principal::anonymous(),
+            text: "Unranked".to_string(),
+            completed: false,
+            created_at: 1,
+            updated_at: 1,
+            priority: Some(Priority::Low),
+            due_at: None,
+            tags: vec!["home".to_string()],
+            list_id: Some(2),
+            parent_id: None,
+        };
+        let mut bytes = vec![TODO_ENCODING_VERSION_V2];
+        bytes.extend(Encode!(&v2).unwrap());
+
+        let todo = Todo::from_bytes(Cow::Owned(bytes));
+        assert_eq!(todo.list_id, Some(2));
+        assert_eq!(todo.tags, vec!["home"]);
+        assert!(todo.rank.is_empty());
+    }
+
+    #[test]
+    fn version_3_config_keeps_its_limits_and_defaults_the_rest() {
+        let v3 = ConfigV3 {
+            max_batch_size: 10,
+            trash_retention_nanos: 60,
+            max_todos_per_owner: 50,
+        };
+        let mut bytes = vec![CONFIG_ENCODING_VERSION_V3];
+        bytes.extend(Encode!(&v3).unwrap());
+
+        let config = Config::from_bytes(Cow::Owned(bytes));
+        assert_eq!(config.max_batch_size, 10);
+        assert_eq!(config.trash_retention_nanos, 60);
+        assert_eq!(config.max_todos_per_owner, 50);
+        assert_eq!(config.max_page_limit, Config::default().max_page_limit);
+    }
+
+    #[test]
+    fn version_4_config_refuses_anonymous_callers() {
+        let v4 = ConfigV4 {
+            max_todo_text_length: 200,
+            max_page_limit: 20,
+            max_todos_per_owner: 50,
+            max_batch_size: 10,
+            trash_retention_nanos: 1,
+        };
+        let mut bytes = vec![CONFIG_ENCODING_VERSION_V4];
+        bytes.extend(Encode!(&v4).unwrap());
+
+        let config = Config::from_bytes(Cow::Owned(bytes));
+        assert_eq!(config.max_todo_text_length, 200);
+        assert_eq!(config.max_batch_size, 10);
+        assert_eq!(config.rate_limit_burst, DEFAULT_RATE_LIMIT_BURST);
+        assert!(!config.allow_anonymous);
+    }
+
+    #[test]
+    fn current_records_round_trip() {
+        let todo = Todo {
+            id: 1,
+            owner: Principal::anonymous(),
+            text: "Plan sprint".to_string(),
+            completed: false,
+            created_at: 1,
+            updated_at: 1,
+            priority: Some(Priority::High),
+            due_at: Some(10),
+            tags: vec!["work".to_string()],
+            list_id: Some(3),
+            parent_id: Some(7),
+            rank: "V".to_string(),
+            recurrence: Some(Recurrence::Weekly {
+                weekdays: vec![Weekday::Monday, Weekday::Friday],
+            }),
+            remind_at: Some(5),
+            version: 3,
+        };
+        assert_eq!(Todo::from_bytes(todo.to_bytes()), todo);
+    }
+
+    #[test]
+    fn version_3_records_decode_at_version_0() {
+        let v3 = TodoV3 {
+            id: 9,
+            owner: Principal::anonymous(),
+            text: "Ranked".to_string(),
+            completed: false,
+            created_at: 1,
+            updated_at: 4,
+            priority: None,
+            due_at: Some(10),
+            tags: Vec::new(),
+            list_id: None,
+            parent_id: None,
+            rank: "V".to_string(),
+            recurrence: Some(Recurrence::Daily),
+            remind_at: Some(5),
+        };
+        let mut bytes = vec![TODO_ENCODING_VERSION_V3];
+        bytes.extend(Encode!(&v3).unwrap());
+
+        let todo = Todo::from_bytes(Cow::Owned(bytes));
+        assert_eq!(todo.rank, "V");
+        assert_eq!(todo.recurrence, Some(Recurrence::Daily));
+        assert_eq!(todo.version, 0);
+    }
+
+    #[test]
+    fn baseline_json_records_decode_and_reencode_as_candid() {
+        // A record exactly as the first release wrote it, before ownership.
+        let json =
+            br#"{"id":4,"text":"Buy milk","completed":true,"created_at":10,"updated_at":20}"#;
+
+        let todo = Todo::from_bytes(Cow::Borrowed(json));
+        assert_eq!(todo.id, 4);
+        assert_eq!(todo.text, "Buy milk");
+        assert!(todo.completed);
+        assert_eq!((todo.created_at, todo.updated_at), (10, 20));
+        assert_eq!(todo.owner, Principal::anonymous());
+        assert!(todo.tags.is_empty());
+        assert!(todo.rank.is_empty());
+
+        // What the re-encoding migration writes back.
+        let bytes = todo.to_bytes();
+        assert_eq!(bytes[0], TODO_ENCODING_VERSION);
+        assert_eq!(Todo::from_bytes(bytes), todo);
+    }
+}